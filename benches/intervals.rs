@@ -0,0 +1,43 @@
+use cargo_diff_tools::intersect_intervals;
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
+
+const INTERVALS: usize = 10_000;
+
+fn bench_intersect_intervals(c: &mut Criterion) {
+    // Intervals of 3 lines every 10 lines, with some empty ones for the deleted lines
+    let intervals: Vec<(usize, usize)> = (0..INTERVALS)
+        .map(|i| (1 + i * 10, if i % 7 == 0 { 0 } else { 3 }))
+        .collect();
+    let last_line = INTERVALS * 10;
+    let mut group = c.benchmark_group("intersect_intervals");
+    for (name, (start, end)) in [
+        ("before the first interval", (0, 0)),
+        ("first interval", (1, 2)),
+        (
+            "middle, intersecting",
+            (last_line / 2 + 1, last_line / 2 + 5),
+        ),
+        (
+            "middle, between intervals",
+            (last_line / 2 + 5, last_line / 2 + 9),
+        ),
+        ("last interval", (last_line - 9, last_line - 8)),
+        (
+            "after the last interval",
+            (last_line + 100, last_line + 200),
+        ),
+        ("whole file", (1, last_line)),
+    ] {
+        group.bench_with_input(
+            BenchmarkId::from_parameter(name),
+            &(start, end),
+            |b, &(start, end)| {
+                b.iter(|| intersect_intervals(black_box(start), black_box(end), &intervals))
+            },
+        );
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_intersect_intervals);
+criterion_main!(benches);
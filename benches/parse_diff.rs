@@ -0,0 +1,44 @@
+use cargo_diff_tools::parse_diff;
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+use std::fmt::Write;
+
+const FILES: usize = 1000;
+const HUNKS: usize = 50;
+
+/// A diff of `FILES` files, each with `HUNKS` hunks that replace two lines with three.
+fn synthetic_diff() -> String {
+    let mut diff = String::new();
+    for file in 0..FILES {
+        writeln!(diff, "diff --git a/src/file_{0}.rs b/src/file_{0}.rs", file).unwrap();
+        writeln!(diff, "index 1a2b3c4..5d6e7f8 100644").unwrap();
+        writeln!(diff, "--- a/src/file_{}.rs", file).unwrap();
+        writeln!(diff, "+++ b/src/file_{}.rs", file).unwrap();
+        for hunk in 0..HUNKS {
+            let line = 1 + hunk * 20;
+            writeln!(
+                diff,
+                "@@ -{},2 +{},3 @@ fn f{}() {{",
+                line,
+                line + hunk,
+                hunk
+            )
+            .unwrap();
+            diff.push_str("-    let a = 1;\n-    let b = 2;\n");
+            diff.push_str("+    let a = 1;\n+    let b = 2;\n+    let c = a + b;\n");
+        }
+    }
+    diff
+}
+
+fn bench_parse_diff(c: &mut Criterion) {
+    let diff = synthetic_diff();
+    let mut group = c.benchmark_group("parse_diff");
+    group.throughput(Throughput::Bytes(diff.len() as u64));
+    group.bench_function("1000 files, 50 hunks each", |b| {
+        b.iter(|| parse_diff(&diff).unwrap())
+    });
+    group.finish();
+}
+
+criterion_group!(benches, bench_parse_diff);
+criterion_main!(benches);
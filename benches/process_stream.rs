@@ -0,0 +1,132 @@
+use cargo_diff_tools::{
+    process_stream, DiagnosticFilter, FileChanges, OutputKind, ProcessOptions, SortKey,
+};
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+use std::io;
+
+const LINES: usize = 10_000;
+const CORPUS_LINES: usize = 5_000;
+
+/// A warning of clippy collected from a real project, which is the seed of the fuzzer.
+const CLIPPY_WARNING: &str = include_str!("../fuzz/corpus/diagnostic/clippy_warning");
+
+/// A warning of clippy at the given position, as printed by `cargo clippy --message-format=json`.
+fn warning_json(file_name: &str, line: usize) -> String {
+    serde_json::json!({
+        "reason": "compiler-message",
+        "message": {
+            "rendered": format!("warning: unused variable: `x`\n --> {}:{}:9\n", file_name, line),
+            "message": "unused variable: `x`",
+            "code": {"code": "unused_variables", "explanation": null},
+            "level": "warning",
+            "spans": [{
+                "file_name": file_name,
+                "line_start": line,
+                "line_end": line,
+                "column_start": 9,
+                "column_end": 10,
+                "is_primary": true
+            }],
+            "children": []
+        }
+    })
+    .to_string()
+}
+
+/// The options of `filter-by-diff` without arguments, except `quiet`.
+fn default_options() -> ProcessOptions {
+    ProcessOptions {
+        filter: DiagnosticFilter::new(),
+        fail_on_warnings: false,
+        warnings_as_errors: false,
+        dedup: false,
+        max_errors: None,
+        output: OutputKind::Rendered,
+        extra_outputs: vec![],
+        color: false,
+        quiet: true,
+        skip_non_json_lines: false,
+        passthrough: true,
+        test_output: false,
+        pathspecs: vec![],
+        baseline: None,
+        save_baseline: None,
+        cache: None,
+        report_existing: false,
+        sort_by: None,
+        group_by_file: false,
+        limit: None,
+    }
+}
+
+fn bench_process_stream(c: &mut Criterion) {
+    let mut file_changes = FileChanges::new();
+    for file in 0..100 {
+        file_changes.insert(format!("src/file_{}.rs", file), vec![(1, 50), (200, 20)]);
+    }
+    let stream: String = (0..LINES)
+        .map(|i| warning_json(&format!("src/file_{}.rs", i % 200), i % 300) + "\n")
+        .collect();
+    let options = default_options();
+
+    let mut group = c.benchmark_group("process_stream");
+    group.throughput(Throughput::Elements(LINES as u64));
+    for (name, options) in [
+        ("streaming", options.clone()),
+        (
+            "sorted",
+            ProcessOptions {
+                sort_by: Some(SortKey::Line),
+                ..options.clone()
+            },
+        ),
+        (
+            "sarif",
+            ProcessOptions {
+                output: OutputKind::Sarif,
+                ..options.clone()
+            },
+        ),
+    ] {
+        group.bench_function(name, |b| {
+            b.iter(|| {
+                process_stream(stream.as_bytes(), &mut io::sink(), &file_changes, &options).unwrap()
+            })
+        });
+    }
+    group.finish();
+}
+
+/// The full pipeline on the lines of a real `cargo clippy` run, with their children, suggestions
+/// and package metadata, moved to other lines and files.
+fn bench_corpus(c: &mut Criterion) {
+    let mut file_changes = FileChanges::new();
+    for file in 0..25 {
+        file_changes.insert(format!("src/file_{}.rs", file), vec![(1, 10)]);
+    }
+    let stream: String = (0..CORPUS_LINES)
+        .map(|i| {
+            CLIPPY_WARNING
+                .trim_end()
+                .replace("src/main.rs", &format!("src/file_{}.rs", i % 50))
+                .replace(
+                    "\"line_end\":2,\"line_start\":2",
+                    &format!("\"line_end\":{0},\"line_start\":{0}", 1 + i % 20),
+                )
+                + "\n"
+        })
+        .collect();
+    let options = default_options();
+
+    let mut group = c.benchmark_group("process_stream_corpus");
+    group.throughput(Throughput::Elements(CORPUS_LINES as u64));
+    group.bench_function("rendered", |b| {
+        b.iter(|| {
+            process_stream(stream.as_bytes(), &mut io::sink(), &file_changes, &options).unwrap()
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, bench_process_stream, bench_corpus);
+criterion_main!(benches);
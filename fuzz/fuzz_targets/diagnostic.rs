@@ -0,0 +1,14 @@
+#![no_main]
+
+use cargo_diff_tools::Diagnostic;
+use libfuzzer_sys::fuzz_target;
+
+// Any line printed by cargo, complete or not, must be either parsed or rejected, never panic
+fuzz_target!(|data: &[u8]| {
+    let line = String::from_utf8_lossy(data);
+    if let Ok(diagnostic) = serde_json::from_str::<Diagnostic>(&line) {
+        let _ = diagnostic.primary_span();
+        let _ = diagnostic.lint_code();
+        let _ = diagnostic.rendered();
+    }
+});
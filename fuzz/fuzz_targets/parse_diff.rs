@@ -0,0 +1,9 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+// Malformed diffs must be reported as errors, never panic
+fuzz_target!(|data: &[u8]| {
+    let diff = String::from_utf8_lossy(data);
+    let _ = cargo_diff_tools::parse_diff(&diff);
+});
@@ -0,0 +1,124 @@
+use crate::diagnostics::Diagnostic;
+use anyhow::{Context, Result};
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+/// The diagnostics that existed before adopting the tool, to be suppressed.
+///
+/// Diagnostics are matched by file, lint code and text, ignoring the line: the line numbers
+/// change whenever some code is added above, whereas the text of a diagnostic usually mentions
+/// the item it is about. Each diagnostic of the baseline suppresses at most one diagnostic.
+#[derive(Clone, Default, PartialEq, Eq, Debug)]
+pub struct Baseline {
+    counts: HashMap<String, usize>,
+}
+
+impl Baseline {
+    /// Read a baseline from JSON diagnostics, one per line. Lines that are not diagnostics (e.g.
+    /// cargo's `build-finished` message) are ignored.
+    pub fn parse(content: &str) -> Result<Baseline> {
+        let mut baseline = Baseline::default();
+        for line in content.lines().filter(|line| !line.trim().is_empty()) {
+            let diagnostic: Diagnostic = serde_json::from_str(line)
+                .with_context(|| format!("Failed to parse JSON diagnostic {:?}", line))?;
+            if let Some(key) = baseline_key(&diagnostic) {
+                *baseline.counts.entry(key).or_default() += 1;
+            }
+        }
+        Ok(baseline)
+    }
+
+    pub fn from_file(path: &Path) -> Result<Baseline> {
+        let content = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read baseline file {:?}", path))?;
+        Baseline::parse(&content)
+            .with_context(|| format!("Failed to parse baseline file {:?}", path))
+    }
+
+    /// Return `true` iff the diagnostic is in the baseline, consuming the match.
+    pub fn suppress(&mut self, diagnostic: &Diagnostic) -> bool {
+        let count = match baseline_key(diagnostic).and_then(|key| self.counts.get_mut(&key)) {
+            Some(count) if *count > 0 => count,
+            _ => return false,
+        };
+        *count -= 1;
+        true
+    }
+
+    /// Return the number of diagnostics in the baseline that have not been matched yet.
+    pub fn len(&self) -> usize {
+        self.counts.values().sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// Identify a diagnostic by file, lint code and text, but not by line.
+fn baseline_key(diagnostic: &Diagnostic) -> Option<String> {
+    let message = diagnostic.message.as_ref()?;
+    let file_name = diagnostic
+        .primary_span()
+        .map(|span| span.file_name.as_str())
+        .unwrap_or_default();
+    Some(format!(
+        "{}:{}:{}",
+        file_name,
+        diagnostic.lint_code().unwrap_or_default(),
+        message.message
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn warning(file_name: &str, line: usize, text: &str) -> String {
+        serde_json::json!({
+            "reason": "compiler-message",
+            "message": {
+                "rendered": format!("warning: {}", text),
+                "message": text,
+                "code": { "code": "unused_variables", "explanation": null },
+                "level": "warning",
+                "spans": [{
+                    "file_name": file_name,
+                    "line_start": line,
+                    "line_end": line,
+                    "column_start": 9,
+                    "column_end": 10,
+                    "is_primary": true
+                }]
+            }
+        })
+        .to_string()
+    }
+
+    fn diagnostic(json_line: &str) -> Diagnostic {
+        serde_json::from_str(json_line).unwrap()
+    }
+
+    #[test]
+    fn test_baseline_suppress() {
+        let content = [
+            warning("src/lib.rs", 10, "unused variable: `x`"),
+            warning("src/lib.rs", 20, "unused variable: `x`"),
+            r#"{"reason":"build-finished","success":true}"#.to_string(),
+        ]
+        .join("\n");
+        let mut baseline = Baseline::parse(&content).unwrap();
+        assert_eq!(baseline.len(), 2);
+
+        // The lines may have shifted, but each diagnostic of the baseline is matched only once
+        let shifted = diagnostic(&warning("src/lib.rs", 13, "unused variable: `x`"));
+        assert!(baseline.suppress(&shifted));
+        assert!(baseline.suppress(&shifted));
+        assert!(!baseline.suppress(&shifted));
+        assert!(baseline.is_empty());
+
+        let other = diagnostic(&warning("src/lib.rs", 10, "unused variable: `y`"));
+        assert!(!Baseline::parse(&content).unwrap().suppress(&other));
+    }
+}
@@ -1,6 +1,10 @@
-use anyhow::Result;
-use cargo_diff_tools::build_app;
+use cargo_diff_tools::{build_app, exit_code};
+use std::process;
 
-fn main() -> Result<()> {
-    build_app(env!("CARGO_BIN_NAME"), Some(("cargo", &["check"])))
+fn main() {
+    if let Err(err) = build_app(env!("CARGO_BIN_NAME"), Some(("cargo", &["check"]))) {
+        // Like the `Result` returned by `main`, with the exit status code of the error
+        eprintln!("Error: {:?}", err);
+        process::exit(exit_code(&err));
+    }
 }
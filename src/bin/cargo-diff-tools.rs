@@ -0,0 +1,6 @@
+use anyhow::Result;
+use cargo_diff_tools::build_generate_app;
+
+fn main() -> Result<()> {
+    build_generate_app(env!("CARGO_BIN_NAME"))
+}
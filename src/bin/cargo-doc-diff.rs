@@ -0,0 +1,10 @@
+use cargo_diff_tools::{build_app, exit_code};
+use std::process;
+
+fn main() {
+    if let Err(err) = build_app(env!("CARGO_BIN_NAME"), Some(("cargo", &["doc"]))) {
+        // Like the `Result` returned by `main`, with the exit status code of the error
+        eprintln!("Error: {:?}", err);
+        process::exit(exit_code(&err));
+    }
+}
@@ -0,0 +1,128 @@
+use crate::diagnostics::Diagnostic;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashSet};
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+
+/// The default directory of the cache of the incremental mode.
+pub const DEFAULT_CACHE_DIR: &str = ".cargo-diff-tools-cache";
+
+/// The name of the file that stores the state of the last run, in the cache directory.
+const STATE_FILE_NAME: &str = "state.json";
+
+#[derive(Default, Serialize, Deserialize)]
+struct State {
+    /// The keys of the diagnostics reported by the last run.
+    reported: BTreeSet<String>,
+}
+
+/// The diagnostics reported by the last run, to tell the new diagnostics from the existing ones.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Cache {
+    state_path: PathBuf,
+    previous: HashSet<String>,
+    current: BTreeSet<String>,
+}
+
+impl Cache {
+    /// Load the state of the last run from the cache directory. The state is empty if there was
+    /// no previous run.
+    pub fn load(dir: &Path) -> Result<Cache> {
+        let state_path = dir.join(STATE_FILE_NAME);
+        let state: State = match fs::read_to_string(&state_path) {
+            Ok(content) => serde_json::from_str(&content)
+                .with_context(|| format!("Failed to parse cache file {:?}", state_path))?,
+            Err(err) if err.kind() == ErrorKind::NotFound => State::default(),
+            Err(err) => {
+                return Err(err)
+                    .with_context(|| format!("Failed to read cache file {:?}", state_path))
+            }
+        };
+        Ok(Cache {
+            state_path,
+            previous: state.reported.into_iter().collect(),
+            current: BTreeSet::new(),
+        })
+    }
+
+    /// Return `true` iff the diagnostic was reported by the last run.
+    pub fn contains(&self, diagnostic: &Diagnostic) -> bool {
+        cache_key(diagnostic).is_some_and(|key| self.previous.contains(&key))
+    }
+
+    /// Record that the diagnostic is reported by this run.
+    pub fn record(&mut self, diagnostic: &Diagnostic) {
+        if let Some(key) = cache_key(diagnostic) {
+            self.current.insert(key);
+        }
+    }
+
+    /// Save the diagnostics recorded by this run, for the next one.
+    pub fn save(&self) -> Result<()> {
+        if let Some(dir) = self.state_path.parent() {
+            fs::create_dir_all(dir)
+                .with_context(|| format!("Failed to create cache directory {:?}", dir))?;
+        }
+        let state = State {
+            reported: self.current.clone(),
+        };
+        fs::write(&self.state_path, serde_json::to_string_pretty(&state)?)
+            .with_context(|| format!("Failed to write cache file {:?}", self.state_path))
+    }
+}
+
+/// Identify a diagnostic by the file and lines of its primary span, and by its lint code (or its
+/// text, if it has no code).
+fn cache_key(diagnostic: &Diagnostic) -> Option<String> {
+    let message = diagnostic.message.as_ref()?;
+    let (file_name, line_start, line_end) = diagnostic
+        .primary_span()
+        .map(|span| (span.file_name.as_str(), span.line_start, span.line_end))
+        .unwrap_or_default();
+    let lint = diagnostic.lint_code().unwrap_or(&message.message);
+    Some(format!(
+        "{}:{}-{}:{}",
+        file_name, line_start, line_end, lint
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{env, process};
+
+    fn warning(line: usize) -> Diagnostic {
+        serde_json::from_value(serde_json::json!({
+            "message": {
+                "message": "unused variable: `x`",
+                "code": { "code": "unused_variables", "explanation": null },
+                "level": "warning",
+                "spans": [{
+                    "file_name": "src/lib.rs",
+                    "line_start": line,
+                    "line_end": line,
+                    "column_start": 9,
+                    "column_end": 10,
+                    "is_primary": true
+                }]
+            }
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_cache_record() {
+        let dir = env::temp_dir().join(format!("cargo-diff-tools-cache-{}", process::id()));
+        let mut cache = Cache::load(&dir).unwrap();
+        assert!(!cache.contains(&warning(10)));
+        cache.record(&warning(10));
+        cache.save().unwrap();
+
+        let cache = Cache::load(&dir).unwrap();
+        assert!(cache.contains(&warning(10)));
+        assert!(!cache.contains(&warning(20)));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,1589 @@
+use crate::baseline::Baseline;
+use crate::cache::{Cache, DEFAULT_CACHE_DIR};
+use crate::config::Config;
+use crate::error::{AppError, CONFIG_EXIT_CODE};
+use crate::filter::canonicalize_path;
+use crate::providers::github::is_pull_request;
+use crate::providers::gitlab::is_merge_request;
+use crate::providers::{
+    git_command, DiffProvider, FileDiffProvider, GitDiffProvider, GitHubApiDiffProvider,
+    GitLabApiDiffProvider, StdinDiffProvider,
+};
+use crate::{
+    parse_diff, process_stream, ColorChoice, DiagnosticFilter, DiffTool, FileChanges, OutputKind,
+    ProcessOptions, SortKey,
+};
+use anyhow::{anyhow, bail, Context, Result};
+use clap::{crate_description, crate_version, value_t, App, AppSettings, Arg, ArgMatches};
+use log::{debug, warn, LevelFilter};
+use std::path::{Path, PathBuf};
+use std::process::{self, Child, Command, Stdio};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::thread;
+use std::time::Duration;
+use std::{
+    env,
+    fs::{self, File},
+    io::{self, BufReader, BufWriter, Write},
+};
+
+/// Cargo failed to complete exit status code per:
+/// https://github.com/rust-lang/cargo/blob/master/src/doc/src/commands/cargo.md
+const CARGO_FAILED_EXIT_CODE: i32 = 101;
+
+/// The exit status codes, as documented in `EXIT_CODES.md`.
+const EXIT_CODES_HELP: &str = "EXIT CODES:
+    0      No diagnostics were reported in the diff, or `--exit-zero` was passed
+    1      Another failure, e.g. the output cannot be written
+    2      The arguments or the configuration are invalid
+    3      The diff cannot be obtained, e.g. because `git diff` failed
+    4      The subprocess cannot be run, or failed without reporting compilation errors
+    5      Diagnostics were reported in the diff
+    124    The subprocess was killed by `--timeout`";
+
+/// Return the command-line interface of the binaries that filter the diagnostics.
+pub(crate) fn diff_app(binary_name: &str) -> App<'static, 'static> {
+    App::new(binary_name)
+        .version(crate_version!())
+        .author(env!("CARGO_PKG_AUTHORS"))
+        .about(crate_description!())
+        .setting(AppSettings::TrailingVarArg)
+        .setting(AppSettings::AllowLeadingHyphen)
+        .after_help(EXIT_CODES_HELP)
+        .arg(
+            Arg::with_name("output")
+                .short("o")
+                .long("output")
+                .env("CARGO_DIFF_TOOLS_OUTPUT")
+                .value_name("FORMAT")
+                .help("Format of the output (default: auto, i.e. `github` in GitHub Actions, `rendered` in a terminal and `json` otherwise). Other formats can be written to files at the same time with `FORMAT=PATH`, e.g. `-o rendered,json=diagnostics.json`")
+                .multiple(true)
+                .number_of_values(1)
+                .use_delimiter(true)
+                .validator(is_output),
+        )
+        .arg(
+            Arg::with_name("color")
+                .long("color")
+                .env("CARGO_DIFF_TOOLS_COLOR")
+                .value_name("WHEN")
+                .help("Whether to color the rendered messages (default: auto, i.e. only in a terminal)")
+                .possible_values(&ColorChoice::variants())
+                .case_insensitive(true),
+        )
+        .arg(
+            Arg::with_name("output-file")
+                .long("output-file")
+                .env("CARGO_DIFF_TOOLS_OUTPUT_FILE")
+                .value_name("PATH")
+                .help("Write the diagnostics to a file instead of the standard output"),
+        )
+        .arg(
+            Arg::with_name("severity")
+                .long("severity")
+                .env("CARGO_DIFF_TOOLS_SEVERITY")
+                .value_name("LEVEL")
+                .help("Only report the diagnostics of the given level (default: all)")
+                .possible_values(&[
+                    "ice",
+                    "error",
+                    "warning",
+                    "failure-note",
+                    "note",
+                    "help",
+                    "all",
+                ])
+                .case_insensitive(true)
+                .multiple(true)
+                .number_of_values(1),
+        )
+        .arg(
+            flag("quiet", "Do not print informational messages, only the diagnostics [env: CARGO_DIFF_TOOLS_QUIET]")
+                .short("q"),
+        )
+        .arg(
+            Arg::with_name("config")
+                .long("config")
+                .env("CARGO_DIFF_TOOLS_CONFIG")
+                .value_name("FILE")
+                .help("Read the default options from the given file (default: the closest `.cargo-diff-tools.toml` in the current directory or its parents)"),
+        )
+        .arg(
+            flag("verbose", "Print debug information to the standard error (see also `RUST_LOG`) [env: CARGO_DIFF_TOOLS_VERBOSE]")
+                .short("v"),
+        )
+        .arg(
+            flag("fail-on-warnings", "Count every warning in the changed lines as a failure, even if it was not reported [env: CARGO_DIFF_TOOLS_FAIL_ON_WARNINGS]"),
+        )
+        .arg(
+            flag("warnings-as-errors", "Report the warnings in the changed lines as errors [env: CARGO_DIFF_TOOLS_WARNINGS_AS_ERRORS]"),
+        )
+        .arg(
+            Arg::with_name("max-errors")
+                .long("max-errors")
+                .env("CARGO_DIFF_TOOLS_MAX_ERRORS")
+                .value_name("N")
+                .help("Stop after reporting N errors")
+                .validator(is_positive_integer),
+        )
+        .arg(
+            Arg::with_name("sort-by")
+                .long("sort-by")
+                .env("CARGO_DIFF_TOOLS_SORT_BY")
+                .value_name("FIELD")
+                .help("Report the diagnostics sorted by file (then line), line, severity (errors first) or lint, once the compiler has finished")
+                .possible_values(&SortKey::variants())
+                .case_insensitive(true),
+        )
+        .arg(
+            flag("group-by-file", "Report the diagnostics of each file consecutively, once the compiler has finished, after a header with the file name on the standard error [env: CARGO_DIFF_TOOLS_GROUP_BY_FILE]"),
+        )
+        .arg(
+            flag("streaming", "Report the diagnostics as soon as the compiler emits them, ignoring --sort-by and --group-by-file, unless the output is a document [env: CARGO_DIFF_TOOLS_STREAMING]")
+                .conflicts_with("limit"),
+        )
+        .arg(
+            Arg::with_name("limit")
+                .long("limit")
+                .env("CARGO_DIFF_TOOLS_LIMIT")
+                .value_name("N")
+                .help("Report only the first N diagnostics, once the compiler has finished (unlike --max-errors, which stops it)")
+                .validator(is_positive_integer),
+        )
+        .arg(
+            flag("workspace", "Pass `--workspace` to cargo, to check all the packages of the workspace [env: CARGO_DIFF_TOOLS_WORKSPACE]"),
+        )
+        .arg(
+            Arg::with_name("package")
+                .short("p")
+                .long("package")
+                .env("CARGO_DIFF_TOOLS_PACKAGE")
+                .value_name("SPEC")
+                .help("Pass `--package SPEC` to cargo, to only check the given package")
+                .multiple(true)
+                .number_of_values(1),
+        )
+        .arg(
+            flag("doctest", "Pass `--doc` to `cargo test`, to only run the doctests; for `cargo-test-diff` only [env: CARGO_DIFF_TOOLS_DOCTEST]"),
+        )
+        .arg(
+            Arg::with_name("features")
+                .long("features")
+                .env("CARGO_DIFF_TOOLS_FEATURES")
+                .value_name("FEATURES")
+                .help("Pass `--features FEATURES` to cargo, to activate the given features (comma-separated)")
+                .multiple(true)
+                .number_of_values(1),
+        )
+        .arg(
+            flag("all-features", "Pass `--all-features` to cargo, to activate all the features [env: CARGO_DIFF_TOOLS_ALL_FEATURES]"),
+        )
+        .arg(
+            flag("no-default-features", "Pass `--no-default-features` to cargo, to not activate the default features [env: CARGO_DIFF_TOOLS_NO_DEFAULT_FEATURES]"),
+        )
+        .arg(
+            flag("keep-going", "Pass `--keep-going` to cargo, to build as many crates as possible after a compilation error, and so report their diagnostics too [env: CARGO_DIFF_TOOLS_KEEP_GOING]"),
+        )
+        .arg(
+            Arg::with_name("target")
+                .long("target")
+                .env("CARGO_DIFF_TOOLS_TARGET")
+                .value_name("TRIPLE")
+                .help("Pass `--target TRIPLE` to cargo, to check for the given target; the triple is removed from the paths of the diagnostics"),
+        )
+        .arg(
+            Arg::with_name("profile")
+                .long("profile")
+                .env("CARGO_DIFF_TOOLS_PROFILE")
+                .value_name("PROFILE")
+                .help("Pass `--profile PROFILE` to cargo, to check with the given profile"),
+        )
+        .arg(
+            Arg::with_name("timeout")
+                .long("timeout")
+                .env("CARGO_DIFF_TOOLS_TIMEOUT")
+                .value_name("SECONDS")
+                .help("Kill the subprocess if it does not terminate within the given number of seconds")
+                .validator(is_positive_integer),
+        )
+        .arg(
+            Arg::with_name("ignore-exit-code")
+                .long("ignore-exit-code")
+                .env("CARGO_DIFF_TOOLS_IGNORE_EXIT_CODE")
+                .value_name("CODE")
+                .help("Do not fail when the subprocess terminates with the given exit code, like when cargo fails to compile (101)")
+                .multiple(true)
+                .number_of_values(1)
+                .validator(is_integer),
+        )
+        .arg(
+            Arg::with_name("allow")
+                .long("allow")
+                .env("CARGO_DIFF_TOOLS_ALLOW")
+                .value_name("LINT")
+                .help("Never report the given lint (e.g. `clippy::too_many_arguments`, or just `too_many_arguments`)")
+                .multiple(true)
+                .number_of_values(1),
+        )
+        .arg(
+            Arg::with_name("deny")
+                .long("deny")
+                .env("CARGO_DIFF_TOOLS_DENY")
+                .value_name("LINT")
+                .help("Always report the given lint, even outside of the changed lines")
+                .multiple(true)
+                .number_of_values(1),
+        )
+        .arg(
+            Arg::with_name("include-files")
+                .long("include-files")
+                .env("CARGO_DIFF_TOOLS_INCLUDE_FILES")
+                .value_name("GLOB")
+                .help("Only report the diagnostics in the files that match the pattern")
+                .multiple(true)
+                .number_of_values(1),
+        )
+        .arg(
+            Arg::with_name("exclude-files")
+                .long("exclude-files")
+                .env("CARGO_DIFF_TOOLS_EXCLUDE_FILES")
+                .value_name("GLOB")
+                .help("Never report the diagnostics in the files that match the pattern")
+                .multiple(true)
+                .number_of_values(1),
+        )
+        .arg(
+            Arg::with_name("context-lines")
+                .long("context-lines")
+                .env("CARGO_DIFF_TOOLS_CONTEXT_LINES")
+                .value_name("N")
+                .help("Also report the diagnostics up to N lines away from the changed lines (default: 0)")
+                .validator(is_non_negative_integer),
+        )
+        .arg(
+            flag("invert", "Only report the diagnostics that are not related to the changed lines [env: CARGO_DIFF_TOOLS_INVERT]"),
+        )
+        .arg(
+            flag("exit-zero", "Exit with status code 0 even if diagnostics are reported, e.g. to only review them in CI; the other failures are still reported [env: CARGO_DIFF_TOOLS_EXIT_ZERO]"),
+        )
+        .arg(
+            flag("follow-children", "Also report the diagnostics whose notes or help messages point to the changed lines [env: CARGO_DIFF_TOOLS_FOLLOW_CHILDREN]"),
+        )
+        .arg(
+            flag("resolve-symlinks", "Resolve the symlinks in the paths of the diff and of the diagnostics before comparing them, which reads the file system [env: CARGO_DIFF_TOOLS_RESOLVE_SYMLINKS]"),
+        )
+        .arg(
+            Arg::with_name("stdin-format")
+                .long("stdin-format")
+                .env("CARGO_DIFF_TOOLS_STDIN_FORMAT")
+                .value_name("FORMAT")
+                .help("Read the diagnostics from the standard input, in the given `--message-format` of cargo, instead of running cargo")
+                .possible_values(&["json", "json-rendered-ansi"])
+                .conflicts_with("diff-stdin"),
+        )
+        .arg(
+            Arg::with_name("diagnostics-file")
+                .long("diagnostics-file")
+                .env("CARGO_DIFF_TOOLS_DIAGNOSTICS_FILE")
+                .value_name("PATH")
+                .help("Read the JSON diagnostics from a file instead of running cargo")
+                .conflicts_with("stdin-format"),
+        )
+        .arg(
+            Arg::with_name("baseline-file")
+                .long("baseline-file")
+                .env("CARGO_DIFF_TOOLS_BASELINE_FILE")
+                .value_name("PATH")
+                .help("Do not report the diagnostics that are in the given JSON diagnostics (e.g. saved by `--save-baseline`), even if their line moved"),
+        )
+        .arg(
+            Arg::with_name("save-baseline")
+                .long("save-baseline")
+                .env("CARGO_DIFF_TOOLS_SAVE_BASELINE")
+                .value_name("PATH")
+                .help("Save all the diagnostics, related to the changes or not, to be used as `--baseline-file` later"),
+        )
+        .arg(
+            flag("incremental", "Only report the diagnostics that were not reported by the last run, as recorded in the cache directory (default: `.cargo-diff-tools-cache`) [env: CARGO_DIFF_TOOLS_INCREMENTAL]"),
+        )
+        .arg(
+            Arg::with_name("cache-dir")
+                .long("cache-dir")
+                .env("CARGO_DIFF_TOOLS_CACHE_DIR")
+                .value_name("PATH")
+                .help("The cache directory of the incremental mode, which is enabled by this option"),
+        )
+        .arg(
+            flag("report-existing", "In the incremental mode, report the diagnostics that were already reported by the last run too [env: CARGO_DIFF_TOOLS_REPORT_EXISTING]"),
+        )
+        .arg(
+            flag("skip-non-json-lines", "Warn about the input lines that are not JSON diagnostics, instead of failing [env: CARGO_DIFF_TOOLS_SKIP_NON_JSON_LINES]"),
+        )
+        .arg(
+            flag("no-passthrough", "Do not write the JSON messages of cargo that are not diagnostics, such as `compiler-artifact` and `build-finished`, to the JSON output [env: CARGO_DIFF_TOOLS_NO_PASSTHROUGH]"),
+        )
+        .arg(
+            flag("no-dedup", "Report again the diagnostics with the same location and lint code [env: CARGO_DIFF_TOOLS_NO_DEDUP]"),
+        )
+        .arg(
+            Arg::with_name("diff-tool")
+                .long("diff-tool")
+                .env("CARGO_DIFF_TOOLS_DIFF_TOOL")
+                .value_name("TOOL")
+                .help("Version control system to use to obtain the diff")
+                .possible_values(&DiffTool::variants())
+                .case_insensitive(true),
+        )
+        .arg(
+            Arg::with_name("base-ref")
+                .long("base-ref")
+                .env("CARGO_DIFF_TOOLS_BASE_REF")
+                .value_name("REF")
+                .help("Only consider the changes since the merge base with the given git ref (e.g. `origin/main`)")
+                .validator(is_git_ref)
+                .conflicts_with_all(&["diff-tool", "args"]),
+        )
+        .arg(
+            flag("staged", "Only consider the changes that are staged for the next commit [env: CARGO_DIFF_TOOLS_STAGED]")
+                .conflicts_with_all(&["diff-tool", "base-ref", "args"]),
+        )
+        .arg(
+            Arg::with_name("from-ref")
+                .long("from-ref")
+                .env("CARGO_DIFF_TOOLS_FROM_REF")
+                .value_name("REF")
+                .help("Only consider the changes since the given git ref (requires `--to-ref`)")
+                .validator(is_git_ref)
+                .requires("to-ref")
+                .conflicts_with_all(&["diff-tool", "base-ref", "staged", "args"]),
+        )
+        .arg(
+            Arg::with_name("to-ref")
+                .long("to-ref")
+                .env("CARGO_DIFF_TOOLS_TO_REF")
+                .value_name("REF")
+                .help("Only consider the changes up to the given git ref (requires `--from-ref`)")
+                .validator(is_git_ref)
+                .requires("from-ref")
+                .conflicts_with_all(&["diff-tool", "base-ref", "staged", "args"]),
+        )
+        .arg(
+            Arg::with_name("since")
+                .long("since")
+                .env("CARGO_DIFF_TOOLS_SINCE")
+                .value_name("COMMIT_ISH")
+                .help("Only consider the changes since the given commit (e.g. `HEAD~1`), or since the merge base with the given branch")
+                .validator(is_git_ref)
+                .conflicts_with_all(&["diff-tool", "base-ref", "staged", "from-ref", "to-ref", "merge-base", "args"]),
+        )
+        .arg(
+            Arg::with_name("merge-base")
+                .long("merge-base")
+                .env("CARGO_DIFF_TOOLS_MERGE_BASE")
+                .value_name("BRANCH")
+                .help("Only consider the changes since the common ancestor of `HEAD` and the given branch, including the uncommitted ones")
+                .validator(is_git_ref)
+                .conflicts_with_all(&["diff-tool", "base-ref", "staged", "from-ref", "to-ref", "args"]),
+        )
+        .arg(
+            Arg::with_name("pathspec")
+                .long("pathspec")
+                .env("CARGO_DIFF_TOOLS_PATHSPEC")
+                .value_name("PATTERN")
+                .help("Only consider the changes of the paths that match the git pathspec")
+                .multiple(true)
+                .number_of_values(1)
+                .conflicts_with("diff-tool"),
+        )
+        .arg(
+            Arg::with_name("git-binary")
+                .long("git-binary")
+                .env("GIT_BINARY")
+                .value_name("PATH")
+                .help("The git executable to run (default: `git`, looked up in PATH)"),
+        )
+        .arg(
+            Arg::with_name("cargo-binary")
+                .long("cargo-binary")
+                .env("CARGO_BINARY")
+                .value_name("PATH")
+                .help("The cargo executable to run (default: `cargo`, looked up in PATH)"),
+        )
+        .arg(
+            Arg::with_name("git-dir")
+                .long("git-dir")
+                .env("CARGO_DIFF_TOOLS_GIT_DIR")
+                .value_name("PATH")
+                .help("Path to the git repository (i.e. the `.git` directory) to diff")
+                .conflicts_with("diff-tool"),
+        )
+        .arg(
+            Arg::with_name("work-tree")
+                .long("work-tree")
+                .env("CARGO_DIFF_TOOLS_WORK_TREE")
+                .value_name("PATH")
+                .help("Run `git diff` and the subprocess in the given working tree")
+                .conflicts_with("diff-tool"),
+        )
+        .arg(
+            Arg::with_name("diff-file")
+                .long("diff-file")
+                .env("CARGO_DIFF_TOOLS_DIFF_FILE")
+                .value_name("PATH")
+                .help("Read the unified diff from a file instead of running `git diff`")
+                .takes_value(true)
+                .conflicts_with_all(&[
+                    "diff-tool",
+                    "base-ref",
+                    "staged",
+                    "from-ref",
+                    "to-ref",
+                    "merge-base",
+                    "since",
+                    "git-dir",
+                    "args",
+                    "git-diff-extra",
+                ]),
+        )
+        .arg(
+            flag("diff-stdin", "Read the unified diff from the standard input instead of running `git diff` [env: CARGO_DIFF_TOOLS_DIFF_STDIN]")
+                .conflicts_with_all(&[
+                    "diff-tool",
+                    "diff-file",
+                    "base-ref",
+                    "staged",
+                    "from-ref",
+                    "to-ref",
+                    "merge-base",
+                    "since",
+                    "git-dir",
+                    "args",
+                    "git-diff-extra",
+                ]),
+        )
+        .arg(
+            Arg::with_name("github-pr")
+                .long("github-pr")
+                .env("CARGO_DIFF_TOOLS_GITHUB_PR")
+                .value_name("OWNER/REPO#NUMBER")
+                .help("Fetch the diff of a GitHub pull request from the GitHub API instead of running `git diff`, authenticating with the GITHUB_TOKEN environment variable if set")
+                .validator(is_pull_request)
+                .conflicts_with_all(&[
+                    "diff-tool",
+                    "diff-file",
+                    "diff-stdin",
+                    "base-ref",
+                    "staged",
+                    "from-ref",
+                    "to-ref",
+                    "merge-base",
+                    "since",
+                    "pathspec",
+                    "git-dir",
+                    "args",
+                    "git-diff-extra",
+                ]),
+        )
+        .arg(
+            Arg::with_name("gitlab-mr")
+                .long("gitlab-mr")
+                .env("CARGO_DIFF_TOOLS_GITLAB_MR")
+                .value_name("PROJECT!IID")
+                .help("Fetch the diff of a GitLab merge request from the GitLab API instead of running `git diff`, authenticating with the GITLAB_TOKEN environment variable if set")
+                .validator(is_merge_request)
+                .conflicts_with_all(&[
+                    "diff-tool",
+                    "diff-file",
+                    "diff-stdin",
+                    "github-pr",
+                    "base-ref",
+                    "staged",
+                    "from-ref",
+                    "to-ref",
+                    "merge-base",
+                    "since",
+                    "pathspec",
+                    "git-dir",
+                    "args",
+                    "git-diff-extra",
+                ]),
+        )
+        .arg(
+            Arg::with_name("git-diff-extra")
+                .long("git-diff-extra")
+                .value_name("ARG")
+                .help("Pass an additional argument to `git diff` (or `hg diff`, or `svn diff`), e.g. `--git-diff-extra=--ignore-all-space`")
+                .multiple(true)
+                .number_of_values(1)
+                .allow_hyphen_values(true),
+        )
+        .arg(
+            Arg::with_name("args")
+                .value_name("GIT_DIFF_ARGS")
+                .help("Additional arguments to pass to `git diff` (or `hg diff`); deprecated, use `--git-diff-extra`, `--since` or `--from-ref` and `--to-ref` instead")
+                .multiple(true),
+        )
+}
+
+/// Parse the arguments of a binary that filters the diagnostics, and run it.
+///
+/// The diagnostics are read from the output of `subcommand`, which is run with the arguments
+/// that follow `--`, or from the standard input if there is no subcommand.
+pub fn build_app(binary_name: &str, subcommand: Option<(&str, &[&str])>) -> Result<()> {
+    let app = CliApp {
+        binary_name,
+        subcommand,
+    };
+    let args: Vec<String> = env::args().collect();
+    let args = match app.parse_args(&args.iter().map(String::as_str).collect::<Vec<_>>()) {
+        Ok(args) => args,
+        Err(err) => match err.downcast::<clap::Error>() {
+            // Let clap print the help and the version, as usual
+            Ok(err) if !err.use_stderr() => err.exit(),
+            Ok(err) => {
+                eprintln!("{}", err);
+                process::exit(CONFIG_EXIT_CODE);
+            }
+            Err(err) => return Err(AppError::Config(err).into()),
+        },
+    };
+    app.run(&args)
+}
+
+/// A binary that filters the diagnostics of `subcommand`, or of the standard input if there is
+/// no subcommand.
+#[derive(Clone, Copy, Debug)]
+pub struct CliApp<'a> {
+    pub binary_name: &'a str,
+    pub subcommand: Option<(&'a str, &'a [&'a str])>,
+}
+
+/// Where the diagnostics are read from.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum DiagnosticsInput {
+    /// The output of a subprocess, run with `args`, the `--message-format` argument and then
+    /// `extra_args`.
+    Subprocess {
+        program: String,
+        args: Vec<String>,
+        extra_args: Vec<String>,
+    },
+    File(PathBuf),
+    Stdin,
+}
+
+/// Where the diff is read from.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum DiffSource {
+    File(PathBuf),
+    Stdin,
+    /// A GitHub pull request, given as `OWNER/REPO#NUMBER`.
+    GitHub(String),
+    /// A GitLab merge request, given as `PROJECT!IID`.
+    GitLab(String),
+    /// The output of `git diff` (or `hg diff`, or `svn diff`) with the given arguments, followed
+    /// by the merge base of `HEAD` and `merge_base` and then by the pathspecs.
+    Command {
+        tool: DiffTool,
+        args: Vec<String>,
+        merge_base: Option<String>,
+    },
+}
+
+/// The parsed arguments of a [`CliApp`].
+#[derive(Clone, Debug)]
+pub struct CliArgs {
+    pub input: DiagnosticsInput,
+    pub diff: DiffSource,
+    pub git_binary: PathBuf,
+    pub work_tree: Option<PathBuf>,
+    pub git_dir: Option<PathBuf>,
+    /// The directory that the paths of the diff are relative to, if they must be made absolute.
+    pub path_base: Option<PathBuf>,
+    pub timeout: Option<Duration>,
+    /// The exit codes of the subprocess that are not failures, besides the one of cargo when the
+    /// compilation fails.
+    pub ignored_exit_codes: Vec<i32>,
+    pub invert: bool,
+    /// The package ID specifications passed to cargo with `--package`.
+    pub packages: Vec<String>,
+    /// Exit successfully even if diagnostics are reported.
+    pub exit_zero: bool,
+    pub output_file: Option<PathBuf>,
+    pub options: ProcessOptions,
+}
+
+impl<'a> CliApp<'a> {
+    /// Parse the arguments of the binary, the first one being its name, and read the
+    /// configuration of the project. This also initializes the logger.
+    pub fn parse_args(&self, args: &[&str]) -> Result<CliArgs> {
+        // Rip off the arguments to be passed to the subcommand
+        let (app_args, subcommand_extra_args) = match args.iter().position(|&v| v == "--") {
+            Some(split_pos) if self.subcommand.is_some() => {
+                (&args[..split_pos], &args[(split_pos + 1)..])
+            }
+            _ => (args, &[][..]),
+        };
+
+        // Parse the argument of this binary
+        let matches = diff_app(self.binary_name).get_matches_from_safe(app_args)?;
+
+        // Log to stderr, as configured by `RUST_LOG`
+        let mut logger =
+            env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("warn"));
+        if flag_enabled(&matches, "verbose") {
+            logger.filter_module(module_path!(), LevelFilter::Debug);
+        }
+        logger.format_timestamp(None).try_init().ok();
+
+        let git_binary = PathBuf::from(matches.value_of("git-binary").unwrap_or("git"));
+        let cargo_binary = matches.value_of("cargo-binary").unwrap_or("cargo");
+
+        // Diagnostics that were already collected are read from a file or from the standard input
+        let diagnostics_file = matches.value_of("diagnostics-file");
+        let mut input = if diagnostics_file.is_some() || matches.is_present("stdin-format") {
+            if !subcommand_extra_args.is_empty() {
+                bail!("The arguments after `--` cannot be used with `--diagnostics-file` or `--stdin-format`, since cargo is not run");
+            }
+            if let Some(format) = matches.value_of("stdin-format") {
+                debug!("Reading {} diagnostics from the standard input", format);
+            }
+            match diagnostics_file {
+                Some(path) => DiagnosticsInput::File(PathBuf::from(path)),
+                None => DiagnosticsInput::Stdin,
+            }
+        } else if let Some((program, args)) = self.subcommand {
+            DiagnosticsInput::Subprocess {
+                program: match program {
+                    "cargo" => cargo_binary.to_string(),
+                    program => program.to_string(),
+                },
+                args: args.iter().map(|arg| arg.to_string()).collect(),
+                extra_args: subcommand_extra_args
+                    .iter()
+                    .map(|arg| arg.to_string())
+                    .collect(),
+            }
+        } else {
+            DiagnosticsInput::Stdin
+        };
+
+        // Read the default options of the project, from `Cargo.toml` first and then from the
+        // configuration file
+        let current_dir =
+            env::current_dir().with_context(|| "Failed to obtain the current directory")?;
+        let config_path = match matches.value_of("config") {
+            Some(path) => Some(PathBuf::from(path)),
+            None => Config::discover(&current_dir),
+        };
+        let mut config = match config_path {
+            Some(path) => {
+                debug!("Reading the configuration file {:?}", path);
+                Config::from_file(&path)?
+            }
+            None => Config::default(),
+        };
+        // `cargo metadata` is slow, so it is only run when cargo is run anyway
+        if matches!(input, DiagnosticsInput::Subprocess { .. }) {
+            match Config::from_cargo_metadata(cargo_binary, &current_dir) {
+                Ok(Some(metadata_config)) => config = metadata_config.or(config),
+                Ok(None) => {}
+                // Not every diff belongs to a Cargo workspace
+                Err(err) => debug!("Ignoring the workspace metadata: {:#}", err),
+            }
+        }
+
+        // The options of cargo that are options of this binary are passed after the subcommand
+        let packages = values_or(&matches, "package", &config.packages);
+        let cargo_args = cargo_args(&matches, &config, &packages);
+        match input {
+            DiagnosticsInput::Subprocess {
+                ref mut args,
+                ref extra_args,
+                ..
+            } => {
+                // Without JSON events, the failed tests would be skipped with the rest of the
+                // output of the test harness
+                if args.first().map(String::as_str) == Some("test")
+                    && !has_json_test_format(extra_args)
+                {
+                    bail!("`cargo test` only reports the failed tests in JSON with a nightly toolchain; pass `-- -Z unstable-options --format=json` to the test harness, e.g. `cargo-test-diff -- -- -Z unstable-options --format=json` with `RUSTUP_TOOLCHAIN=nightly`");
+                }
+                if flag_enabled(&matches, "doctest") {
+                    if args.first().map(String::as_str) != Some("test") {
+                        bail!("`--doctest` can only be used with `cargo test`");
+                    }
+                    args.push("--doc".to_string());
+                }
+                args.extend(cargo_args)
+            }
+            _ if !cargo_args.is_empty() || flag_enabled(&matches, "doctest") => bail!("`--doctest`, `--workspace`, `--package`, `--features`, `--all-features`, `--no-default-features`, `--keep-going`, `--target` and `--profile` cannot be used when the diagnostics are read from a file or from the standard input, since cargo is not run"),
+            _ => {}
+        }
+        let runs_subprocess = matches!(input, DiagnosticsInput::Subprocess { .. });
+
+        // Read the levels of the diagnostics to report
+        let mut filter = DiagnosticFilter::new();
+        let severities = values_or(&matches, "severity", &config.severity);
+        if !severities.iter().any(|v| v.eq_ignore_ascii_case("all")) {
+            for value in severities {
+                filter = filter.with_level(value.parse()?);
+            }
+        }
+
+        let allowed_lints = values_or(&matches, "allow", &config.allowed_lints);
+        let denied_lints = values_or(&matches, "deny", &config.denied_lints);
+        filter = filter
+            .with_allowed_lints(&allowed_lints.iter().map(String::as_str).collect::<Vec<_>>())
+            .with_denied_lints(&denied_lints.iter().map(String::as_str).collect::<Vec<_>>());
+        for pattern in values_or(&matches, "include-files", &config.include_files) {
+            filter = filter.with_file_include_glob(&pattern)?;
+        }
+        for pattern in values_or(&matches, "exclude-files", &config.exclude_files) {
+            filter = filter.with_file_exclude_glob(&pattern)?;
+        }
+        if let Some(target) = value_or(&matches, "target", config.target.as_deref()) {
+            filter = filter.with_target(target);
+        }
+        let invert = flag_enabled(&matches, "invert");
+        filter = filter
+            .with_follow_children(flag_enabled(&matches, "follow-children"))
+            .with_invert(invert);
+        let context_lines = config.context_lines.map(|n| n.to_string());
+        if let Some(context_lines) = value_or(&matches, "context-lines", context_lines.as_deref()) {
+            filter =
+                filter.with_context_lines(context_lines.parse().with_context(|| {
+                    format!("Invalid number of context lines {:?}", context_lines)
+                })?);
+        }
+        let config_outputs = config
+            .output
+            .as_ref()
+            .map(|output| output.split(',').map(String::from).collect());
+        let mut output = None;
+        let mut extra_outputs = vec![];
+        for value in values_or(&matches, "output", &config_outputs) {
+            let (kind, path) = parse_output(&value)?;
+            match path {
+                // The environment says nothing about the format that suits a file
+                Some(path) if kind == OutputKind::Auto => {
+                    extra_outputs.push((OutputKind::Json, path))
+                }
+                Some(path) => extra_outputs.push((kind, path)),
+                None if output.is_none() => output = Some(kind),
+                None => bail!(
+                    "At most one output format can be written to the {}; write the other ones to files with `--output=FORMAT=PATH`",
+                    if matches.is_present("output-file") { "output file" } else { "standard output" }
+                ),
+            }
+        }
+        let output = output.unwrap_or(OutputKind::Auto);
+
+        let timeout = matches
+            .value_of("timeout")
+            .map(|n| Duration::from_secs(n.parse().expect("Validated by clap")));
+        if timeout.is_some() && !runs_subprocess {
+            bail!("`--timeout` cannot be used when the diagnostics are read from a file or from the standard input");
+        }
+        let ignored_exit_codes: Vec<i32> = matches
+            .values_of("ignore-exit-code")
+            .unwrap_or_default()
+            .map(|code| code.parse().expect("Validated by clap"))
+            .collect();
+        if !ignored_exit_codes.is_empty() && !runs_subprocess {
+            bail!("`--ignore-exit-code` cannot be used when the diagnostics are read from a file or from the standard input");
+        }
+
+        let git_dir = matches
+            .value_of("git-dir")
+            .map(|path| {
+                fs::canonicalize(path)
+                    .with_context(|| format!("Failed to find git directory {:?}", path))
+            })
+            .transpose()?;
+        let work_tree = matches
+            .value_of("work-tree")
+            .map(|path| {
+                fs::canonicalize(path)
+                    .with_context(|| format!("Failed to find working tree {:?}", path))
+            })
+            .transpose()?;
+
+        let diff_tool = value_t!(matches, "diff-tool", DiffTool).unwrap_or(DiffTool::Git);
+        let diff = if let Some(diff_file) = matches.value_of("diff-file") {
+            DiffSource::File(PathBuf::from(diff_file))
+        } else if let Some(pull_request) = matches.value_of("github-pr") {
+            DiffSource::GitHub(pull_request.to_string())
+        } else if let Some(merge_request) = matches.value_of("gitlab-mr") {
+            DiffSource::GitLab(merge_request.to_string())
+        } else if flag_enabled(&matches, "diff-stdin") {
+            if input == DiagnosticsInput::Stdin {
+                bail!("`--diff-stdin` cannot be used when the diagnostics are read from the standard input");
+            }
+            DiffSource::Stdin
+        } else {
+            // Read `git diff` arguments, the options first
+            let mut git_diff_args: Vec<String> = matches
+                .values_of("git-diff-extra")
+                .unwrap_or_default()
+                .map(String::from)
+                .collect();
+            if let Some(args) = matches.values_of("args") {
+                warn!("The positional arguments of `git diff` are deprecated, since they are easily mistaken for arguments of {}; use `--git-diff-extra`, `--since` or `--from-ref` and `--to-ref` instead", self.binary_name);
+                git_diff_args.extend(args.map(String::from));
+            }
+            if flag_enabled(&matches, "staged") {
+                git_diff_args.push("--cached".to_string());
+            }
+            if let Some(base_ref) = matches.value_of("base-ref") {
+                // The three-dot syntax compares `HEAD` with its merge base with `base_ref`
+                git_diff_args.push(format!("{}...HEAD", base_ref));
+            }
+            if let (Some(from_ref), Some(to_ref)) =
+                (matches.value_of("from-ref"), matches.value_of("to-ref"))
+            {
+                git_diff_args.push(format!("{}..{}", from_ref, to_ref));
+            }
+            if let Some(since) = matches.value_of("since") {
+                git_diff_args.push(if is_commit_ref(since) {
+                    since.to_string()
+                } else {
+                    // The three-dot syntax compares `HEAD` with its merge base with the branch
+                    format!("{}...HEAD", since)
+                });
+            }
+            DiffSource::Command {
+                tool: diff_tool,
+                args: git_diff_args,
+                merge_base: matches.value_of("merge-base").map(String::from),
+            }
+        };
+
+        // Subversion reports paths relative to the working copy, which may not be the root of the
+        // Cargo workspace. Compare absolute paths instead. The same applies to an explicit work
+        // tree, from which the subprocess is run. Canonical paths also have no symlinks.
+        let path_base = if work_tree.is_some() {
+            work_tree.clone()
+        } else if diff_tool == DiffTool::Svn || flag_enabled(&matches, "resolve-symlinks") {
+            Some(current_dir)
+        } else {
+            None
+        };
+        if let Some(ref base) = path_base {
+            filter = filter.with_path_base(base.clone());
+        }
+
+        let output = match output {
+            // The environment says nothing about the format that suits a file
+            OutputKind::Auto if matches.is_present("output-file") => OutputKind::Json,
+            output => output.resolve(),
+        };
+        // Streaming wins over the options that need the whole stream, except in documents
+        let streaming = flag_enabled(&matches, "streaming") && output.is_streaming_compatible();
+
+        let options = ProcessOptions {
+            filter,
+            fail_on_warnings: flag_enabled(&matches, "fail-on-warnings"),
+            warnings_as_errors: flag_enabled(&matches, "warnings-as-errors"),
+            dedup: !flag_enabled(&matches, "no-dedup"),
+            max_errors: matches
+                .value_of("max-errors")
+                .map(|n| n.parse().expect("Validated by clap")),
+            output,
+            color: match value_t!(matches, "color", ColorChoice).unwrap_or(ColorChoice::Auto) {
+                ColorChoice::Auto if matches.is_present("output-file") => false,
+                color => color.is_enabled(),
+            },
+            extra_outputs,
+            quiet: flag_enabled(&matches, "quiet"),
+            skip_non_json_lines: flag_enabled(&matches, "skip-non-json-lines"),
+            passthrough: !flag_enabled(&matches, "no-passthrough"),
+            test_output: match input {
+                DiagnosticsInput::Subprocess { ref args, .. } => {
+                    args.first().map(String::as_str) == Some("test")
+                }
+                _ => false,
+            },
+            baseline: matches
+                .value_of("baseline-file")
+                .map(|path| Baseline::from_file(Path::new(path)))
+                .transpose()?,
+            save_baseline: matches.value_of("save-baseline").map(PathBuf::from),
+            cache: if flag_enabled(&matches, "incremental") || matches.is_present("cache-dir") {
+                Some(Cache::load(Path::new(
+                    matches.value_of("cache-dir").unwrap_or(DEFAULT_CACHE_DIR),
+                ))?)
+            } else {
+                None
+            },
+            report_existing: flag_enabled(&matches, "report-existing"),
+            limit: matches
+                .value_of("limit")
+                .map(|n| n.parse().expect("Validated by clap")),
+            group_by_file: flag_enabled(&matches, "group-by-file") && !streaming,
+            sort_by: matches
+                .value_of("sort-by")
+                .filter(|_| !streaming)
+                .map(|key| key.parse().expect("Validated by clap")),
+            pathspecs: matches
+                .values_of("pathspec")
+                .unwrap_or_default()
+                .map(String::from)
+                .collect(),
+        };
+
+        Ok(CliArgs {
+            input,
+            diff,
+            git_binary,
+            work_tree,
+            git_dir,
+            path_base,
+            timeout,
+            ignored_exit_codes,
+            invert,
+            packages,
+            exit_zero: flag_enabled(&matches, "exit-zero"),
+            output_file: matches.value_of("output-file").map(PathBuf::from),
+            options,
+        })
+    }
+
+    /// Read the diff and return the lines that it changes.
+    pub fn obtain_diff(&self, args: &CliArgs) -> Result<FileChanges> {
+        let diff = self.diff_provider(args)?.get_diff()?;
+
+        let file_changes = parse_diff(&diff)?;
+        Ok(match args.path_base {
+            Some(ref base) => file_changes.map_paths(|path| canonicalize_path(base, path)),
+            None => file_changes,
+        })
+    }
+
+    /// Return the provider of the diff selected by the arguments.
+    pub fn diff_provider(&self, args: &CliArgs) -> Result<Box<dyn DiffProvider>> {
+        Ok(match args.diff {
+            DiffSource::File(ref path) => Box::new(FileDiffProvider { path: path.clone() }),
+            // The whole diff is read before spawning the subprocess
+            DiffSource::Stdin => Box::new(StdinDiffProvider),
+            DiffSource::GitHub(ref pull_request) => {
+                Box::new(GitHubApiDiffProvider::from_spec(pull_request)?)
+            }
+            DiffSource::GitLab(ref merge_request) => {
+                Box::new(GitLabApiDiffProvider::from_spec(merge_request)?)
+            }
+            DiffSource::Command {
+                tool,
+                args: ref diff_args,
+                ref merge_base,
+            } => {
+                let mut git_diff_args = diff_args.clone();
+                if let Some(branch) = merge_base {
+                    git_diff_args.push(self.merge_base(args, branch)?);
+                }
+                if !args.options.pathspecs.is_empty() {
+                    if !git_diff_args.iter().any(|arg| arg == "--") {
+                        git_diff_args.push("--".to_string());
+                    }
+                    git_diff_args.extend(args.options.pathspecs.iter().cloned());
+                }
+                Box::new(GitDiffProvider {
+                    tool,
+                    args: git_diff_args,
+                    git_binary: args.git_binary.clone(),
+                    work_tree: args.work_tree.clone(),
+                    git_dir: args.git_dir.clone(),
+                })
+            }
+        })
+    }
+
+    /// Return the SHA of the merge base of `HEAD` and `branch`.
+    fn merge_base(&self, args: &CliArgs, branch: &str) -> Result<String> {
+        let output = git_command(
+            &args.git_binary,
+            args.work_tree.as_deref(),
+            args.git_dir.as_deref(),
+        )
+        .args(["merge-base", "HEAD", branch])
+        .output()
+        .with_context(|| "Failed to start `git merge-base`")?;
+        if !output.status.success() {
+            io::stderr()
+                .write_all(&output.stderr)
+                .with_context(|| "Failed to report the stderr of `git merge-base`")?;
+            bail!(
+                "`git merge-base HEAD {}` terminated with exit status {:?}",
+                branch,
+                output.status.code().unwrap_or(-1)
+            );
+        }
+        let merge_base = String::from_utf8_lossy(&output.stdout).trim().to_string();
+        debug!("Merge base of HEAD and {}: {}", branch, merge_base);
+        Ok(merge_base)
+    }
+
+    /// Spawn the subprocess that reports the diagnostics, with its standard output piped.
+    pub fn run_subprocess(&self, args: &CliArgs) -> Result<Child> {
+        let (program, subcommand_args, extra_args) = match args.input {
+            DiagnosticsInput::Subprocess {
+                ref program,
+                ref args,
+                ref extra_args,
+            } => (program, args, extra_args),
+            _ => bail!("The diagnostics are not read from a subprocess"),
+        };
+        let json_arg = if args.options.color && args.options.output.is_colored() {
+            // Colored
+            "--message-format=json-diagnostic-rendered-ansi"
+        } else {
+            // Colorless
+            "--message-format=json"
+        };
+
+        let mut command = Command::new(program);
+        if let Some(ref work_tree) = args.work_tree {
+            command.current_dir(work_tree);
+        }
+        command
+            .args(subcommand_args)
+            .arg(json_arg)
+            .args(extra_args)
+            .stdout(Stdio::piped()) // filter stdout
+            .stderr(Stdio::inherit()) // do not filter stderr
+            .spawn()
+            .with_context(|| {
+                format!(
+                    "Failed to start subprocess {:?} with arguments {:?}",
+                    program, subcommand_args,
+                )
+            })
+    }
+
+    /// Obtain the diff, then filter and report the diagnostics.
+    pub fn run(&self, args: &CliArgs) -> Result<()> {
+        let file_changes = self.obtain_diff(args).map_err(AppError::Diff)?;
+        for (path, changes) in file_changes.iter() {
+            debug!("Changed lines of {}: {:?}", path, changes);
+        }
+        for package in &args.packages {
+            // E.g. `serde`, `serde@1.0.0` or `https://github.com/serde-rs/serde#serde@1.0.0`
+            let name = package.rsplit('#').next().unwrap_or_default();
+            let name = name.split(['@', ':']).next().unwrap_or_default();
+            if !file_changes.is_empty() && !file_changes.iter().any(|(path, _)| path.contains(name))
+            {
+                warn!(
+                    "No changed file has a path that contains {:?}; check that `--package {}` selects the changed package",
+                    name, package
+                );
+            }
+        }
+
+        // Filter and report JSON diagnostic messages
+        let options = &args.options;
+        // The baseline is made of all the diagnostics, so it must be saved even without changes
+        if file_changes.is_empty() && !args.invert && options.save_baseline.is_none() {
+            options.info("No changes discovered.");
+            return Ok(());
+        }
+        let mut out: Box<dyn Write + Send> = match args.output_file {
+            Some(ref path) => {
+                Box::new(BufWriter::new(File::create(path).with_context(|| {
+                    format!("Failed to create output file {:?}", path)
+                })?))
+            }
+            None => Box::new(io::stdout()),
+        };
+        let result = match args.input {
+            DiagnosticsInput::Subprocess { .. } => {
+                let mut child = self.run_subprocess(args).map_err(AppError::Subprocess)?;
+
+                // Process output
+                let stdout = child
+                    .stdout
+                    .take()
+                    .with_context(|| "Failed to open standard output of subprocess")?;
+                let child = Arc::new(Mutex::new(child));
+                let watchdog = args
+                    .timeout
+                    .map(|timeout| Watchdog::spawn(Arc::clone(&child), timeout));
+                let result =
+                    process_stream(BufReader::new(stdout), &mut out, &file_changes, options);
+
+                // The killed subprocess may leave a partial line, which is not the actual error
+                if watchdog.is_some_and(Watchdog::stop) {
+                    out.flush().with_context(|| "Failed to write the output")?;
+                    return Err(AppError::Timeout {
+                        seconds: args.timeout.unwrap_or_default().as_secs(),
+                    }
+                    .into());
+                }
+                let result = result?;
+                let mut child = child.lock().unwrap_or_else(PoisonError::into_inner);
+                if result.stopped {
+                    // There is no need to wait for the remaining diagnostics
+                    child.kill().with_context(|| "Failed to kill subprocess")?;
+                    child
+                        .wait()
+                        .with_context(|| "Failed to wait for subprocess")?;
+                } else {
+                    // Wait for end of subprocess
+                    let exit_status = child
+                        .wait()
+                        .with_context(|| "Failed to wait for subprocess")?;
+                    // Note that cargo will return non-zero exit code even if the observed diff
+                    // didn't have any errors, thus we're handling this case separately (checking
+                    // for # of returned errors).
+                    let exit_code = exit_status.code().unwrap_or_default();
+                    if !exit_status.success()
+                        && exit_code != CARGO_FAILED_EXIT_CODE
+                        && !args.ignored_exit_codes.contains(&exit_code)
+                    {
+                        return Err(AppError::SubprocessFailed(exit_status).into());
+                    }
+                }
+                result
+            }
+            DiagnosticsInput::File(ref path) => {
+                let file = File::open(path)
+                    .with_context(|| format!("Failed to open diagnostics file {:?}", path))?;
+                process_stream(BufReader::new(file), &mut out, &file_changes, options)?
+            }
+            // Process standard input
+            DiagnosticsInput::Stdin => {
+                process_stream(io::stdin().lock(), &mut out, &file_changes, options)?
+            }
+        };
+        out.flush().with_context(|| "Failed to write the output")?;
+
+        let reported = result.reported;
+        let failure = if result.stopped {
+            Some(anyhow!(
+                "Stopped after {} error(s); run without --max-errors to see all.",
+                reported
+            ))
+        } else if reported > 0 {
+            Some(if args.invert {
+                anyhow!("Found {} error(s) in unchanged code.", reported)
+            } else {
+                anyhow!("Observed git diff resulted in {} error(s).", reported)
+            })
+        } else {
+            None
+        };
+        if let Some(failure) = failure {
+            if !args.exit_zero {
+                return Err(AppError::DiagnosticsFound(failure).into());
+            }
+            if !options.quiet {
+                eprintln!("{} Exiting successfully because of --exit-zero.", failure);
+            }
+            return Ok(());
+        }
+
+        if args.invert {
+            options.info("Success: Didn't find errors in unchanged code.");
+        } else {
+            options.info("Success: Didn't find errors for the observed diff.");
+        }
+        Ok(())
+    }
+}
+
+/// Kill a subprocess if it does not terminate within a timeout.
+struct Watchdog {
+    stop: mpsc::Sender<()>,
+    handle: thread::JoinHandle<bool>,
+}
+
+impl Watchdog {
+    fn spawn(child: Arc<Mutex<Child>>, timeout: Duration) -> Self {
+        let (stop, stopped) = mpsc::channel();
+        let handle = thread::spawn(move || match stopped.recv_timeout(timeout) {
+            Err(RecvTimeoutError::Timeout) => {
+                if let Ok(mut child) = child.lock() {
+                    child.kill().ok();
+                }
+                true
+            }
+            _ => false,
+        });
+        Watchdog { stop, handle }
+    }
+
+    /// Stop the watchdog, returning `true` iff it killed the subprocess.
+    fn stop(self) -> bool {
+        self.stop.send(()).ok();
+        self.handle.join().unwrap_or(false)
+    }
+}
+
+/// Return the values of a command-line argument, or the ones of the configuration if the argument
+/// is missing. The values of the environment variable have the lowest priority.
+fn values_or(matches: &ArgMatches, name: &str, config_values: &Option<Vec<String>>) -> Vec<String> {
+    match config_values {
+        Some(values) if matches.occurrences_of(name) == 0 => values.clone(),
+        _ => matches
+            .values_of(name)
+            .unwrap_or_default()
+            .map(String::from)
+            .collect(),
+    }
+}
+
+/// Return the value of a command-line argument, or the one of the configuration if the argument
+/// is missing. The value of the environment variable has the lowest priority.
+fn value_or<'a>(
+    matches: &'a ArgMatches,
+    name: &str,
+    config_value: Option<&'a str>,
+) -> Option<&'a str> {
+    if matches.occurrences_of(name) == 0 {
+        config_value.or_else(|| matches.value_of(name))
+    } else {
+        matches.value_of(name)
+    }
+}
+
+/// Return the name of the environment variable that can be used instead of an argument.
+fn env_var_name(name: &str) -> String {
+    format!(
+        "CARGO_DIFF_TOOLS_{}",
+        name.to_ascii_uppercase().replace('-', "_")
+    )
+}
+
+/// Return `true` iff the arguments of `cargo test` make the test harness print its events in JSON,
+/// i.e. pass `-Z unstable-options --format=json` after `--`, which requires a nightly toolchain.
+fn has_json_test_format(args: &[String]) -> bool {
+    let harness_args = match args.iter().position(|arg| arg == "--") {
+        Some(index) => &args[index + 1..],
+        None => return false,
+    };
+    let has_pair = |flag: &str, value: &str| {
+        harness_args
+            .windows(2)
+            .any(|pair| pair[0] == flag && pair[1] == value)
+    };
+    let has_arg = |arg: &str| harness_args.iter().any(|a| a == arg);
+    (has_pair("--format", "json") || has_arg("--format=json"))
+        && (has_pair("-Z", "unstable-options") || has_arg("-Zunstable-options"))
+}
+
+/// Return a boolean flag named after its long option. Clap only reads the environment for the
+/// options that take a value, so the help must name the variable read by `flag_enabled`.
+fn flag(name: &'static str, help: &'static str) -> Arg<'static, 'static> {
+    debug_assert!(
+        help.ends_with(&format!(" [env: {}]", env_var_name(name))),
+        "The help of --{} does not name its environment variable",
+        name
+    );
+    Arg::with_name(name).long(name).help(help)
+}
+
+/// Return `true` iff the flag is passed on the command line, or if its environment variable is set
+/// to anything but an empty value, `0` or `false`.
+fn flag_enabled(matches: &ArgMatches, name: &str) -> bool {
+    matches.is_present(name)
+        || env::var(env_var_name(name))
+            .is_ok_and(|value| !matches!(value.as_str(), "" | "0" | "false"))
+}
+
+/// Return the arguments of cargo that are given as options of this binary (or in the
+/// configuration), to be passed after the subcommand.
+fn cargo_args(matches: &ArgMatches, config: &Config, packages: &[String]) -> Vec<String> {
+    let mut args = vec![];
+    if flag_enabled(matches, "workspace") || config.workspace == Some(true) {
+        args.push("--workspace".to_string());
+    }
+    for package in packages {
+        args.push("--package".to_string());
+        args.push(package.to_string());
+    }
+    for features in matches.values_of("features").unwrap_or_default() {
+        args.push("--features".to_string());
+        args.push(features.to_string());
+    }
+    for flag in ["all-features", "no-default-features", "keep-going"] {
+        if flag_enabled(matches, flag) {
+            args.push(format!("--{}", flag));
+        }
+    }
+    for (name, value) in [
+        ("target", config.target.as_deref()),
+        ("profile", config.profile.as_deref()),
+    ] {
+        if let Some(value) = value_or(matches, name, value) {
+            args.push(format!("--{}", name));
+            args.push(value.to_string());
+        }
+    }
+    args
+}
+
+/// Return `true` iff the git ref looks like it designates a commit rather than a branch, i.e. if
+/// it is relative to `HEAD`, contains an ancestry operator (`~` or `^`), or looks like a SHA.
+fn is_commit_ref(git_ref: &str) -> bool {
+    git_ref.starts_with("HEAD")
+        || git_ref.contains(&['~', '^'][..])
+        || (git_ref.len() >= 7 && git_ref.chars().all(|c| c.is_ascii_hexdigit()))
+}
+
+fn is_integer(value: String) -> std::result::Result<(), String> {
+    match value.parse::<i32>() {
+        Ok(_) => Ok(()),
+        _ => Err(format!("{:?} is not an integer", value)),
+    }
+}
+
+fn is_positive_integer(value: String) -> std::result::Result<(), String> {
+    match value.parse::<i32>() {
+        Ok(n) if n > 0 => Ok(()),
+        _ => Err(format!("{:?} is not a positive integer", value)),
+    }
+}
+
+/// Reject the empty git refs and the ones that contain shell metacharacters.
+pub(crate) fn is_git_ref(value: String) -> std::result::Result<(), String> {
+    if value.is_empty() {
+        return Err("The git ref cannot be empty".to_string());
+    }
+    if value.starts_with('-') {
+        return Err(format!("{:?} is not a valid git ref", value));
+    }
+    if let Some(c) = value
+        .chars()
+        .find(|&c| c.is_whitespace() || "`$&|;<>()[]*?!'\"\\".contains(c))
+    {
+        return Err(format!(
+            "{:?} is not a valid git ref: it contains {:?}",
+            value, c
+        ));
+    }
+    Ok(())
+}
+
+/// Parse an output format, optionally followed by `=PATH` to write it to a file.
+fn parse_output(value: &str) -> Result<(OutputKind, Option<PathBuf>)> {
+    let (kind, path) = match value.split_once('=') {
+        Some((_, "")) => bail!("Missing path of output {:?}", value),
+        Some((kind, path)) => (kind, Some(PathBuf::from(path))),
+        None => (value, None),
+    };
+    let kind = kind
+        .parse::<OutputKind>()
+        .map_err(|err| anyhow!("Invalid output format {:?}: {}", value, err))?;
+    Ok((kind, path))
+}
+
+fn is_output(value: String) -> std::result::Result<(), String> {
+    parse_output(&value)
+        .map(|_| ())
+        .map_err(|err| err.to_string())
+}
+
+fn is_non_negative_integer(value: String) -> std::result::Result<(), String> {
+    match value.parse::<usize>() {
+        Ok(_) => Ok(()),
+        _ => Err(format!("{:?} is not a non-negative integer", value)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FILTER_BY_DIFF: CliApp = CliApp {
+        binary_name: "filter-by-diff",
+        subcommand: None,
+    };
+
+    const CARGO_CLIPPY_DIFF: CliApp = CliApp {
+        binary_name: "cargo-clippy-diff",
+        subcommand: Some(("cargo", &["clippy"])),
+    };
+
+    #[test]
+    fn test_is_commit_ref() {
+        assert!(is_commit_ref("HEAD~1"));
+        assert!(is_commit_ref("origin/main^"));
+        assert!(is_commit_ref("3ada2621"));
+        assert!(!is_commit_ref("main"));
+        assert!(!is_commit_ref("origin/feature"));
+    }
+
+    #[test]
+    fn test_has_json_test_format() {
+        let args = |args: &[&str]| args.iter().map(|arg| arg.to_string()).collect::<Vec<_>>();
+        assert!(has_json_test_format(&args(&[
+            "--",
+            "-Z",
+            "unstable-options",
+            "--format=json"
+        ])));
+        assert!(has_json_test_format(&args(&[
+            "--lib",
+            "--",
+            "-Zunstable-options",
+            "--format",
+            "json"
+        ])));
+        assert!(!has_json_test_format(&args(&["--", "--format=json"])));
+        assert!(!has_json_test_format(&args(&["--format=json"])));
+        assert!(!has_json_test_format(&args(&[])));
+    }
+
+    #[test]
+    fn test_parse_args() {
+        let args = CARGO_CLIPPY_DIFF
+            .parse_args(&[
+                "cargo-clippy-diff",
+                "--since",
+                "main",
+                "--output=json",
+                "-p",
+                "cargo-diff-tools",
+                "--features=serde,log",
+                "--no-default-features",
+                "--",
+                "--all-targets",
+            ])
+            .unwrap();
+        assert_eq!(
+            args.input,
+            DiagnosticsInput::Subprocess {
+                program: "cargo".to_string(),
+                args: [
+                    "clippy",
+                    "--package",
+                    "cargo-diff-tools",
+                    "--features",
+                    "serde,log",
+                    "--no-default-features"
+                ]
+                .iter()
+                .map(|arg| arg.to_string())
+                .collect(),
+                extra_args: vec!["--all-targets".to_string()],
+            }
+        );
+        assert_eq!(
+            args.diff,
+            DiffSource::Command {
+                tool: DiffTool::Git,
+                args: vec!["main...HEAD".to_string()],
+                merge_base: None,
+            }
+        );
+        assert_eq!(args.options.output, OutputKind::Json);
+
+        let args = CARGO_CLIPPY_DIFF
+            .parse_args(&[
+                "cargo-clippy-diff",
+                "--git-diff-extra",
+                "--word-diff",
+                "--git-diff-extra=--diff-algorithm=patience",
+                "--since",
+                "HEAD~1",
+            ])
+            .unwrap();
+        assert_eq!(
+            args.diff,
+            DiffSource::Command {
+                tool: DiffTool::Git,
+                args: ["--word-diff", "--diff-algorithm=patience", "HEAD~1"]
+                    .iter()
+                    .map(|arg| arg.to_string())
+                    .collect(),
+                merge_base: None,
+            }
+        );
+        assert!(args.ignored_exit_codes.is_empty());
+        let args = CARGO_CLIPPY_DIFF
+            .parse_args(&[
+                "cargo-clippy-diff",
+                "--ignore-exit-code",
+                "2",
+                "--ignore-exit-code=100",
+            ])
+            .unwrap();
+        assert_eq!(args.ignored_exit_codes, vec![2, 100]);
+
+        // Streaming ignores the sorting, but not in documents
+        let args = FILTER_BY_DIFF
+            .parse_args(&[
+                "filter-by-diff",
+                "--streaming",
+                "--sort-by=line",
+                "--output=json",
+            ])
+            .unwrap();
+        assert_eq!(args.options.sort_by, None);
+        let args = FILTER_BY_DIFF
+            .parse_args(&[
+                "filter-by-diff",
+                "--streaming",
+                "--sort-by=line",
+                "--output=sarif",
+            ])
+            .unwrap();
+        assert_eq!(args.options.sort_by, Some(SortKey::Line));
+
+        let args = CARGO_CLIPPY_DIFF
+            .parse_args(&[
+                "cargo-clippy-diff",
+                "--diagnostics-file",
+                "diagnostics.jsonl",
+            ])
+            .unwrap();
+        assert_eq!(
+            args.input,
+            DiagnosticsInput::File(PathBuf::from("diagnostics.jsonl"))
+        );
+        assert!(CARGO_CLIPPY_DIFF
+            .parse_args(&[
+                "cargo-clippy-diff",
+                "--diagnostics-file",
+                "d.jsonl",
+                "--",
+                "-q"
+            ])
+            .is_err());
+
+        // Without subcommand, the diagnostics are read from the standard input
+        let args = FILTER_BY_DIFF
+            .parse_args(&["filter-by-diff", "--diff-file", "changes.diff"])
+            .unwrap();
+        assert_eq!(args.input, DiagnosticsInput::Stdin);
+        assert_eq!(args.diff, DiffSource::File(PathBuf::from("changes.diff")));
+        assert!(FILTER_BY_DIFF
+            .parse_args(&["filter-by-diff", "--diff-stdin"])
+            .is_err());
+        assert!(FILTER_BY_DIFF
+            .parse_args(&["filter-by-diff", "--all-features"])
+            .is_err());
+        assert!(FILTER_BY_DIFF
+            .parse_args(&["filter-by-diff", "--timeout", "10"])
+            .is_err());
+        assert!(FILTER_BY_DIFF
+            .parse_args(&["filter-by-diff", "--ignore-exit-code", "2"])
+            .is_err());
+    }
+
+    #[test]
+    fn test_obtain_diff() {
+        let diff_file = env::temp_dir().join(format!("cargo-diff-tools-{}.diff", process::id()));
+        fs::write(
+            &diff_file,
+            "diff --git a/src/lib.rs b/src/lib.rs\n--- a/src/lib.rs\n+++ b/src/lib.rs\n@@ -10,0 +11,2 @@\n+a\n+b\n",
+        )
+        .unwrap();
+        let args = FILTER_BY_DIFF
+            .parse_args(&["filter-by-diff", "--diff-file", diff_file.to_str().unwrap()])
+            .unwrap();
+        let file_changes = FILTER_BY_DIFF.obtain_diff(&args).unwrap();
+        fs::remove_file(&diff_file).unwrap();
+        assert_eq!(&file_changes["src/lib.rs"], &[(11, 2)]);
+    }
+}
@@ -0,0 +1,130 @@
+use anyhow::{bail, Context, Result};
+use serde::Deserialize;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// The name of the configuration file, looked up in the current directory and in its parents.
+pub const CONFIG_FILE_NAME: &str = ".cargo-diff-tools.toml";
+
+/// The key of the configuration in the `[workspace.metadata]` section of `Cargo.toml`.
+const METADATA_KEY: &str = "cargo-diff-tools";
+
+/// The default options of a project. The options passed on the command line take precedence.
+#[derive(Clone, Default, PartialEq, Eq, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    pub severity: Option<Vec<String>>,
+    pub include_files: Option<Vec<String>>,
+    pub exclude_files: Option<Vec<String>>,
+    pub context_lines: Option<usize>,
+    pub allowed_lints: Option<Vec<String>>,
+    pub denied_lints: Option<Vec<String>>,
+    pub output: Option<String>,
+    pub workspace: Option<bool>,
+    pub packages: Option<Vec<String>>,
+    pub target: Option<String>,
+    pub profile: Option<String>,
+}
+
+impl Config {
+    pub fn parse(content: &str) -> Result<Config> {
+        Ok(toml::from_str(content)?)
+    }
+
+    pub fn from_file(path: &Path) -> Result<Config> {
+        let content = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read configuration file {:?}", path))?;
+        Config::parse(&content)
+            .with_context(|| format!("Failed to parse configuration file {:?}", path))
+    }
+
+    /// Read the `[workspace.metadata.cargo-diff-tools]` section of the Cargo workspace that
+    /// contains `dir`, if there is any, running the `cargo` executable.
+    pub fn from_cargo_metadata(cargo: &str, dir: &Path) -> Result<Option<Config>> {
+        let output = Command::new(cargo)
+            .args(["metadata", "--no-deps", "--format-version=1"])
+            .current_dir(dir)
+            .output()
+            .with_context(|| "Failed to run `cargo metadata`")?;
+        if !output.status.success() {
+            bail!(
+                "`cargo metadata` failed: {}",
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
+        }
+        let metadata: serde_json::Value = serde_json::from_slice(&output.stdout)
+            .with_context(|| "Failed to parse the output of `cargo metadata`")?;
+        match metadata.get("metadata").and_then(|m| m.get(METADATA_KEY)) {
+            Some(value) => Ok(Some(Config::deserialize(value).with_context(|| {
+                format!(
+                    "Failed to parse [workspace.metadata.{}] of {:?}",
+                    METADATA_KEY,
+                    metadata.get("workspace_root")
+                )
+            })?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Complete the options that are missing with the ones of `defaults`.
+    pub fn or(self, defaults: Config) -> Config {
+        Config {
+            severity: self.severity.or(defaults.severity),
+            include_files: self.include_files.or(defaults.include_files),
+            exclude_files: self.exclude_files.or(defaults.exclude_files),
+            context_lines: self.context_lines.or(defaults.context_lines),
+            allowed_lints: self.allowed_lints.or(defaults.allowed_lints),
+            denied_lints: self.denied_lints.or(defaults.denied_lints),
+            output: self.output.or(defaults.output),
+            workspace: self.workspace.or(defaults.workspace),
+            packages: self.packages.or(defaults.packages),
+            target: self.target.or(defaults.target),
+            profile: self.profile.or(defaults.profile),
+        }
+    }
+
+    /// Find the closest configuration file in `dir` or in its parents, like `rustfmt` does.
+    pub fn discover(dir: &Path) -> Option<PathBuf> {
+        dir.ancestors()
+            .map(|ancestor| ancestor.join(CONFIG_FILE_NAME))
+            .find(|path| path.is_file())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use indoc::indoc;
+
+    #[test]
+    fn test_parse_config() {
+        let config = Config::parse(indoc! {r#"
+            severity = ["error", "warning"]
+            exclude_files = ["tests/**"]
+            context_lines = 2
+            allowed_lints = ["clippy::needless_return"]
+            packages = ["cargo-diff-tools"]
+        "#})
+        .unwrap();
+        assert_eq!(
+            config,
+            Config {
+                severity: Some(vec!["error".to_string(), "warning".to_string()]),
+                exclude_files: Some(vec!["tests/**".to_string()]),
+                context_lines: Some(2),
+                allowed_lints: Some(vec!["clippy::needless_return".to_string()]),
+                packages: Some(vec!["cargo-diff-tools".to_string()]),
+                ..Config::default()
+            }
+        );
+        assert!(Config::parse("severity = \"error\"").is_err());
+        assert!(Config::parse("unknown_option = true").is_err());
+
+        let metadata = serde_json::json!({"severity": ["error"], "output": "github"});
+        let config = Config::deserialize(&metadata).unwrap().or(config);
+        assert_eq!(config.severity, Some(vec!["error".to_string()]));
+        assert_eq!(config.output, Some("github".to_string()));
+        assert_eq!(config.context_lines, Some(2));
+    }
+}
@@ -1,24 +1,64 @@
-use serde::Deserialize;
+use anyhow::bail;
+use lazy_static::lazy_static;
+use regex::Regex;
+use serde::{Deserialize, Deserializer};
+use std::str::FromStr;
+
+/// The `reason` given to the diagnostics built from the failed tests of the test harness. Cargo
+/// does not emit it: the events of the harness have no `reason`, and are only printed in JSON by
+/// `cargo test -- -Z unstable-options --format=json` on nightly.
+pub const TEST_FAILED_REASON: &str = "test-failed";
+
+/// The `reason` of the messages of cargo that wrap a diagnostic of the compiler.
+const COMPILER_MESSAGE_REASON: &str = "compiler-message";
 
 #[derive(Clone, PartialEq, Eq, Hash, Debug, Deserialize)]
 pub struct Diagnostic {
+    /// The kind of cargo message, e.g. `compiler-message`, or `TEST_FAILED_REASON` for the
+    /// diagnostics built from the failed tests.
+    #[serde(default)]
+    pub reason: Option<String>,
     pub message: Option<Message>,
+    /// The output that the compiler would print, if the diagnostic is not wrapped in a cargo
+    /// message (e.g. when produced by `rustc --error-format=json`).
+    #[serde(default)]
+    pub rendered: Option<String>,
 }
 
 #[derive(Clone, PartialEq, Eq, Hash, Debug, Deserialize)]
 pub struct Message {
+    pub message: String,
+    pub code: Option<DiagnosticCode>,
     pub level: Level,
+    /// The message as rendered by the compiler. Empty for the children messages.
+    #[serde(default, deserialize_with = "null_as_default")]
     pub rendered: String,
     pub spans: Vec<Span>,
+    /// The notes and help messages attached to this message.
+    #[serde(default)]
+    pub children: Vec<Message>,
 }
 
-#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug, Deserialize)]
+#[derive(Clone, PartialEq, Eq, Hash, Debug, Deserialize)]
+pub struct DiagnosticCode {
+    /// The lint name or error code, e.g. `clippy::needless_return` or `E0308`.
+    pub code: String,
+    pub explanation: Option<String>,
+}
+
+/// The level of a diagnostic. Levels are ordered by severity, from `Help` to `Ice`.
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Debug, Deserialize)]
 #[serde(rename_all = "lowercase")]
 pub enum Level {
     Help,
     Note,
+    #[serde(rename = "failure-note")]
+    FailureNote,
     Warning,
     Error,
+    /// Internal compiler error.
+    #[serde(rename = "error: internal compiler error")]
+    Ice,
 }
 
 #[derive(Clone, PartialEq, Eq, Hash, Debug, Deserialize)]
@@ -31,9 +71,273 @@ pub struct Span {
     pub is_primary: bool,
 }
 
+impl Level {
+    /// Return the name of the level, as used by the compiler.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Level::Help => "help",
+            Level::Note => "note",
+            Level::FailureNote => "failure-note",
+            Level::Warning => "warning",
+            Level::Error => "error",
+            Level::Ice => "error: internal compiler error",
+        }
+    }
+
+    /// Return `true` iff the level is at least as severe as `minimum`.
+    pub fn is_at_least(self, minimum: Level) -> bool {
+        self >= minimum
+    }
+}
+
+impl FromStr for Level {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "help" => Ok(Level::Help),
+            "note" => Ok(Level::Note),
+            "failure-note" => Ok(Level::FailureNote),
+            "warning" => Ok(Level::Warning),
+            "error" => Ok(Level::Error),
+            "ice" | "error: internal compiler error" => Ok(Level::Ice),
+            _ => bail!("Unknown diagnostic level {:?}", s),
+        }
+    }
+}
+
 impl Message {
     /// Return the first primary span, if there is any.
     pub fn primary_span(&self) -> Option<&Span> {
         self.spans.iter().find(|s| s.is_primary)
     }
 }
+
+impl Diagnostic {
+    /// Parse only the `reason` of a line, and return it as a message without diagnostic if it is a
+    /// message of cargo other than `compiler-message`, e.g. `compiler-artifact` or
+    /// `build-finished`. Return `None` for the other lines, which must be parsed in full.
+    pub fn from_non_diagnostic_line(json_line: &str) -> Option<Diagnostic> {
+        #[derive(Deserialize)]
+        struct CargoMessage {
+            reason: Option<String>,
+        }
+
+        let reason = serde_json::from_str::<CargoMessage>(json_line)
+            .ok()?
+            .reason?;
+        if reason == COMPILER_MESSAGE_REASON {
+            return None;
+        }
+        Some(Diagnostic {
+            reason: Some(reason),
+            message: None,
+            rendered: None,
+        })
+    }
+
+    /// Return `true` iff this is a message of cargo other than a diagnostic, which is passed
+    /// through unchanged.
+    pub fn is_passthrough(&self) -> bool {
+        self.message.is_none()
+            && self
+                .reason
+                .as_deref()
+                .is_some_and(|reason| reason != COMPILER_MESSAGE_REASON)
+    }
+
+    /// Return the first primary span of the message, if there is any.
+    pub fn primary_span(&self) -> Option<&Span> {
+        self.message.as_ref()?.primary_span()
+    }
+
+    /// Return the output that the compiler would print for the diagnostic, if there is any.
+    pub fn rendered(&self) -> Option<&str> {
+        self.rendered.as_deref().or_else(|| {
+            self.message
+                .as_ref()
+                .map(|message| message.rendered.as_str())
+        })
+    }
+
+    /// Return the lint name or error code of the message, if there is any.
+    pub fn lint_code(&self) -> Option<&str> {
+        Some(self.message.as_ref()?.code.as_ref()?.code.as_str())
+    }
+
+    /// Return a key that identifies the diagnostic by the location of its primary span and its
+    /// lint code (or its text, if it has no code). Returns `None` for diagnostics without a
+    /// message.
+    pub fn diagnostic_key(&self) -> Option<String> {
+        let message = self.message.as_ref()?;
+        let (file_name, line_start) = self
+            .primary_span()
+            .map(|span| (span.file_name.as_str(), span.line_start))
+            .unwrap_or_default();
+        let lint = self.lint_code().unwrap_or(&message.message);
+        Some(format!("{}:{}:{}", file_name, line_start, lint))
+    }
+}
+
+/// An event of the JSON output of the test harness, i.e. of
+/// `cargo test -- -Z unstable-options --format=json`, which is unstable and requires nightly.
+#[derive(Deserialize)]
+struct TestEvent {
+    #[serde(rename = "type")]
+    kind: String,
+    event: String,
+    name: Option<String>,
+    /// The output captured from the test, including the panic message.
+    #[serde(default)]
+    stdout: String,
+}
+
+impl Diagnostic {
+    /// Parse a `failed` test event of the test harness as an error located where the test
+    /// panicked. Return `None` for the other lines.
+    pub fn from_test_event(json_line: &str) -> Option<Diagnostic> {
+        lazy_static! {
+            // E.g. `panicked at src/lib.rs:10:5:` or, before Rust 1.73, `panicked at 'msg', src/lib.rs:10:5`
+            static ref PANIC_RE: Regex = Regex::new(
+                r"panicked at (?:'.*', )?(?P<file>[^\s:'][^:\n]*):(?P<line>[0-9]+):(?P<column>[0-9]+)"
+            )
+            .expect("Failed to parse regex");
+        }
+
+        let event: TestEvent = serde_json::from_str(json_line).ok()?;
+        if event.kind != "test" || event.event != "failed" {
+            return None;
+        }
+        let name = event.name.unwrap_or_default();
+        // A doctest is located by its name, since it panics in a file generated by rustdoc
+        let doctest_span = doctest_location(&name).map(|(file_name, line)| Span {
+            file_name: file_name.to_string(),
+            line_start: line,
+            line_end: line,
+            column_start: 1,
+            column_end: 2,
+            is_primary: true,
+        });
+        let stdout = &event.stdout;
+        let spans = doctest_span
+            .or_else(|| {
+                let cap = PANIC_RE.captures(stdout)?;
+                let line = cap["line"].parse().ok()?;
+                let column: usize = cap["column"].parse().ok()?;
+                Some(Span {
+                    file_name: cap["file"].to_string(),
+                    line_start: line,
+                    line_end: line,
+                    column_start: column,
+                    column_end: column + 1,
+                    is_primary: true,
+                })
+            })
+            .into_iter()
+            .collect();
+        let message = format!("test {} failed", name);
+        Some(Diagnostic {
+            reason: Some(TEST_FAILED_REASON.to_string()),
+            rendered: None,
+            message: Some(Message {
+                rendered: format!("error: {}\n{}", message, event.stdout),
+                message,
+                code: None,
+                level: Level::Error,
+                spans,
+                children: vec![],
+            }),
+        })
+    }
+
+    /// Return `true` iff the diagnostic represents a failed test.
+    pub fn is_test_failure(&self) -> bool {
+        self.reason.as_deref() == Some(TEST_FAILED_REASON)
+    }
+}
+
+/// Split the name of a doctest, which rustdoc also uses as the file name of its spans, into the
+/// file and the line of its code block, e.g. `src/lib.rs - foo::bar (line 42)`. Return `None` for
+/// the other names.
+pub(crate) fn doctest_location(name: &str) -> Option<(&str, usize)> {
+    lazy_static! {
+        // The item is missing for the doctests of the crate, and a suffix such as ` - compile
+        // fail` follows the line of some doctests
+        static ref DOCTEST_RE: Regex =
+            Regex::new(r"^(?P<file>.+?\.rs) - .*\(line (?P<line>[0-9]+)\)(?: - .*)?$")
+                .expect("Failed to parse regex");
+    }
+
+    let cap = DOCTEST_RE.captures(name)?;
+    Some((cap.name("file")?.as_str(), cap["line"].parse().ok()?))
+}
+
+/// Deserialize `null` as the default value of the type.
+fn null_as_default<'de, D, T>(deserializer: D) -> Result<T, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Default + Deserialize<'de>,
+{
+    Ok(Option::<T>::deserialize(deserializer)?.unwrap_or_default())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_non_diagnostic_line() {
+        let diagnostic = Diagnostic::from_non_diagnostic_line(
+            r#"{"reason":"compiler-artifact","target":{"name":"foo"},"fresh":true}"#,
+        )
+        .unwrap();
+        assert_eq!(diagnostic.reason.as_deref(), Some("compiler-artifact"));
+        assert!(diagnostic.is_passthrough());
+        assert!(Diagnostic::from_non_diagnostic_line(
+            r#"{"reason":"compiler-message","message":null}"#
+        )
+        .is_none());
+        // Diagnostics of rustc, not wrapped in a message of cargo
+        assert!(Diagnostic::from_non_diagnostic_line(
+            r#"{"message":"unused import","level":"warning"}"#
+        )
+        .is_none());
+        assert!(Diagnostic::from_non_diagnostic_line("not JSON").is_none());
+    }
+
+    #[test]
+    fn test_from_test_event() {
+        let line = r#"{ "type": "test", "name": "tests::it_works", "event": "failed", "stdout": "\nthread 'tests::it_works' panicked at src/lib.rs:10:9:\nassertion `left == right` failed\n" }"#;
+        let diagnostic = Diagnostic::from_test_event(line).unwrap();
+        assert!(diagnostic.is_test_failure());
+        let span = diagnostic.primary_span().unwrap();
+        assert_eq!(
+            (span.file_name.as_str(), span.line_start, span.column_start),
+            ("src/lib.rs", 10, 9)
+        );
+        assert_eq!(
+            diagnostic.message.unwrap().message,
+            "test tests::it_works failed"
+        );
+
+        let line = r#"{ "type": "test", "name": "src/lib.rs - foo::bar (line 42)", "event": "failed", "stdout": "thread 'main' panicked at src/lib.rs:5:1:\n" }"#;
+        let span = Diagnostic::from_test_event(line)
+            .unwrap()
+            .primary_span()
+            .cloned()
+            .unwrap();
+        assert_eq!(
+            (span.file_name.as_str(), span.line_start),
+            ("src/lib.rs", 42)
+        );
+        assert_eq!(
+            doctest_location("src/lib.rs - (line 3) - compile fail"),
+            Some(("src/lib.rs", 3))
+        );
+        assert_eq!(doctest_location("tests::it_works"), None);
+
+        let line = r#"{ "type": "test", "name": "tests::it_works", "event": "ok" }"#;
+        assert!(Diagnostic::from_test_event(line).is_none());
+        assert!(Diagnostic::from_test_event(r#"{"reason":"build-finished"}"#).is_none());
+    }
+}
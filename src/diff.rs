@@ -1,25 +1,412 @@
-use anyhow::{Context, Result};
+use crate::intervals::{merge_overlapping, union_intervals};
+use anyhow::{bail, Context, Result};
+use clap::arg_enum;
 use lazy_static::lazy_static;
+use log::warn;
 use regex::Regex;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::iter::FromIterator;
+use std::ops::Index;
+use std::path::{Component, Path};
 
-pub type FileChanges = HashMap<String, Vec<(usize, usize)>>;
+/// The lines modified by a diff, as an ordered list of `(start, len)` intervals for each file.
+/// The files that have been renamed can also be looked up by their old path.
+#[derive(Clone, Default, PartialEq, Eq, Debug)]
+pub struct FileChanges {
+    changes: HashMap<String, Vec<(usize, usize)>>,
+    /// The new path of each renamed file, by old path.
+    aliases: HashMap<String, String>,
+}
+
+impl FileChanges {
+    pub fn new() -> Self {
+        FileChanges::default()
+    }
+
+    /// Set the modified lines of a file, replacing the previous ones.
+    /// `intervals` must be an ordered list of `(start, len)` pairs.
+    pub fn insert(&mut self, path: String, intervals: Vec<(usize, usize)>) {
+        self.changes.insert(path, intervals);
+    }
+
+    /// Record that the file at `old_path` has been renamed to `new_path`.
+    pub fn insert_alias(&mut self, old_path: String, new_path: String) {
+        self.aliases.insert(old_path, new_path);
+    }
+
+    /// Return the new path of a renamed file.
+    pub fn renamed_to(&self, old_path: &str) -> Option<&str> {
+        self.aliases.get(old_path).map(String::as_str)
+    }
+
+    /// Return the modified lines of a file, if the file has been modified. The path can be either
+    /// the new or the old path of a renamed file.
+    pub fn intervals_for(&self, path: &str) -> Option<&[(usize, usize)]> {
+        self.changes
+            .get(path)
+            .or_else(|| {
+                self.renamed_to(path)
+                    .and_then(|new_path| self.changes.get(new_path))
+            })
+            .map(Vec::as_slice)
+    }
+
+    /// Return `true` iff the file has been modified.
+    pub fn contains_file(&self, path: &str) -> bool {
+        self.intervals_for(path).is_some()
+    }
+
+    /// Iterate over the paths of the modified files, in arbitrary order. Renamed files are listed
+    /// by their new path.
+    pub fn files(&self) -> impl Iterator<Item = &str> {
+        self.changes.keys().map(String::as_str)
+    }
+
+    /// Return the total number of modified lines, across all files. The files changed entirely,
+    /// e.g. binary files, are not counted, since their number of lines is unknown.
+    pub fn line_count(&self) -> usize {
+        self.changes
+            .values()
+            .flat_map(|intervals| intervals.iter())
+            .filter(|&&interval| interval != WHOLE_FILE)
+            .map(|&(_, len)| len)
+            .sum()
+    }
+
+    /// Return the number of modified files.
+    pub fn len(&self) -> usize {
+        self.changes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+
+    /// Iterate over the modified files and their modified lines, in arbitrary order.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &[(usize, usize)])> {
+        self.changes
+            .iter()
+            .map(|(path, intervals)| (path.as_str(), intervals.as_slice()))
+    }
+
+    /// Rewrite the paths of the modified files, and of the old paths of the renamed files.
+    pub fn map_paths<F: Fn(&str) -> String>(self, f: F) -> FileChanges {
+        FileChanges {
+            changes: self
+                .changes
+                .into_iter()
+                .map(|(path, intervals)| (f(&path), intervals))
+                .collect(),
+            aliases: self
+                .aliases
+                .into_iter()
+                .map(|(old_path, new_path)| (f(&old_path), f(&new_path)))
+                .collect(),
+        }
+    }
+}
+
+impl FileChanges {
+    /// Combine the changes of two diffs, e.g. of the staged and of the unstaged changes.
+    pub fn merge(mut self, other: FileChanges) -> FileChanges {
+        for (path, intervals) in other.changes {
+            let existing = self.changes.entry(path).or_default();
+            *existing = union_intervals(existing, &intervals);
+        }
+        self.aliases.extend(other.aliases);
+        self
+    }
+}
+
+impl FromIterator<(String, Vec<(usize, usize)>)> for FileChanges {
+    /// Build the changes from `(path, intervals)` pairs. The intervals don't need to be ordered,
+    /// and the intervals of repeated paths are combined.
+    fn from_iter<I: IntoIterator<Item = (String, Vec<(usize, usize)>)>>(iter: I) -> Self {
+        let mut file_changes = FileChanges::new();
+        for (path, intervals) in iter {
+            let existing = file_changes.changes.entry(path).or_default();
+            *existing = union_intervals(existing, &intervals);
+        }
+        file_changes
+    }
+}
+
+impl Index<&str> for FileChanges {
+    type Output = [(usize, usize)];
+
+    fn index(&self, path: &str) -> &Self::Output {
+        self.intervals_for(path)
+            .unwrap_or_else(|| panic!("No changes for file {:?}", path))
+    }
+}
+
+/// The interval used for the files whose changes can't be described line by line, e.g. binary
+/// files, which are considered to be changed entirely. The interval ends at `usize::MAX`, so the
+/// interval functions use saturating arithmetic on its end.
+const WHOLE_FILE: (usize, usize) = (1, usize::MAX - 1);
+
+arg_enum! {
+    #[derive(Copy, Clone, PartialEq, Eq, Debug)]
+    pub enum DiffTool {
+        Git,
+        Hg,
+        Svn,
+    }
+}
+
+impl DiffTool {
+    /// Return the program and the arguments to use to obtain a diff without context lines.
+    pub fn command(self) -> (&'static str, &'static [&'static str]) {
+        match self {
+            DiffTool::Git => ("git", &["diff", "--unified=0"]),
+            DiffTool::Hg => ("hg", &["diff", "-U", "0"]),
+            DiffTool::Svn => ("svn", &["diff", "--diff-cmd", "diff", "-x", "-U0"]),
+        }
+    }
+}
+
+/// The flavor of a unified diff, which determines the shape of the file headers.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+enum DiffFormat {
+    /// `diff --git a/<file> b/<file>`, followed by `+++ b/<file>`.
+    Git,
+    /// `diff -r <rev> <file>`, followed by `+++ b/<file>\t<date>`.
+    Mercurial,
+    /// `Index: <file>`, followed by `+++ <file>\t(working copy)`.
+    Subversion,
+}
+
+/// Detect the format of the diff from its first `diff` or `Index:` line.
+fn detect_diff_format(diff: &str) -> DiffFormat {
+    match diff
+        .lines()
+        .find(|line| line.starts_with("diff ") || line.starts_with("Index: "))
+    {
+        Some(line) if line.starts_with("diff -r ") => DiffFormat::Mercurial,
+        Some(line) if line.starts_with("Index: ") => DiffFormat::Subversion,
+        _ => DiffFormat::Git,
+    }
+}
+
+/// Return the path with forward slashes and without `.` components, so that the paths of the
+/// diffs and of the diagnostics can be compared on Windows, where tools use either separator.
+pub(crate) fn normalize_path(path: &str) -> String {
+    let path = path.replace('\\', "/");
+    let mut normalized = String::with_capacity(path.len());
+    let mut segments = vec![];
+    for component in Path::new(&path).components() {
+        match component {
+            Component::Prefix(prefix) => normalized.push_str(&prefix.as_os_str().to_string_lossy()),
+            Component::RootDir => normalized.push('/'),
+            Component::CurDir => {}
+            component => segments.push(component.as_os_str().to_string_lossy()),
+        }
+    }
+    normalized.push_str(&segments.join("/"));
+    normalized
+}
+
+/// The position in the body of a hunk, whose lines are prefixed by `+` (added), `-` (deleted) or
+/// a space (context).
+#[derive(Debug)]
+struct Hunk {
+    /// The lines of the old and of the new file that the rest of the body covers.
+    old_remaining: usize,
+    new_remaining: usize,
+    /// The line of the new file of the next added or context line.
+    next_line: usize,
+    /// The range of the header, used if the hunk has no body.
+    header: (usize, usize),
+    has_body: bool,
+    /// Whether the current run of added and deleted lines has some of them.
+    run_has_additions: bool,
+    run_has_deletions: bool,
+}
+
+impl Hunk {
+    fn new(old_len: usize, from: usize, len: usize) -> Self {
+        Hunk {
+            old_remaining: old_len,
+            new_remaining: len,
+            // The start of an empty range is the line before it
+            next_line: if len == 0 {
+                from.saturating_add(1)
+            } else {
+                from
+            },
+            header: (from, len),
+            has_body: false,
+            run_has_additions: false,
+            run_has_deletions: false,
+        }
+    }
+
+    fn is_finished(&self) -> bool {
+        self.old_remaining == 0 && self.new_remaining == 0
+    }
+
+    /// End the current run of added and deleted lines. Return the position of the deleted lines,
+    /// if no line was added in their place.
+    fn end_run(&mut self) -> Option<(usize, usize)> {
+        let deletion = if self.run_has_deletions && !self.run_has_additions {
+            Some((self.next_line.saturating_sub(1), 0))
+        } else {
+            None
+        };
+        self.run_has_additions = false;
+        self.run_has_deletions = false;
+        deletion
+    }
+}
 
 /// Return for each file an ordered list of (start, len) intervals of modified lines.
+///
+/// Only the added lines of the hunks are modified, and the position of the deleted lines is
+/// marked by an empty interval, so the diff may have context lines (e.g. from `git log -p` or from
+/// the API of GitHub). The hunks without body, as printed by some tools, are considered to modify
+/// the whole range of their header.
 pub fn parse_diff(diff: &str) -> Result<FileChanges> {
     lazy_static! {
         static ref RE: Regex = Regex::new(
-            r"^\+\+\+ .?/(?P<filePath>.*)\s*$|^@@ -[0-9]+(,[0-9]+)? \+(?P<linesFrom>[0-9]+)(,(?P<linesLen>[0-9]+))? @@"
+            r"^\+\+\+ .?/(?P<filePath>.*)\s*$|^@@ -[0-9]+(,(?P<oldLen>[0-9]+))? \+(?P<linesFrom>[0-9]+)(,(?P<linesLen>[0-9]+))? @@"
+        ).expect("Failed to parse regex");
+        static ref RENAME_RE: Regex =
+            Regex::new(r"^rename (?P<side>from|to) (?P<filePath>.*)$").expect("Failed to parse regex");
+        // Deleted binary files are compared with `/dev/null`, and have nothing left to report.
+        // Mercurial prints a shorter line, also for the deleted files.
+        static ref BINARY_RE: Regex = Regex::new(
+            r"^Binary files .* and b/(?P<filePath>.*) differ$|^Binary file (?P<hgFilePath>.*) has changed$"
+        ).expect("Failed to parse regex");
+        // Subversion paths have no `a/` or `b/` prefix and are followed by the revision
+        static ref SVN_RE: Regex = Regex::new(
+            r"^\+\+\+ (?P<filePath>[^\t]*)\t|^@@ -[0-9]+(,(?P<oldLen>[0-9]+))? \+(?P<linesFrom>[0-9]+)(,(?P<linesLen>[0-9]+))? @@"
         ).expect("Failed to parse regex");
     }
 
-    let mut file_changes: FileChanges = HashMap::new();
+    let format = detect_diff_format(diff);
+    let re: &Regex = if format == DiffFormat::Subversion {
+        &SVN_RE
+    } else {
+        &RE
+    };
+    let mut file_changes: FileChanges = FileChanges::new();
     let mut curr_file_path = None;
+    let mut renamed_from = None;
+    let mut hunk: Option<Hunk> = None;
+    // The files modified by the current commit and by the previous ones, in a patch log
+    let mut commit_files = HashSet::new();
+    let mut earlier_commit_files = HashSet::new();
+    let mut warned_files = HashSet::new();
     for line in diff.lines() {
-        if let Some(cap) = RE.captures(line) {
+        if let Some(ref mut current) = hunk {
+            let path = curr_file_path
+                .as_ref()
+                .with_context(|| "Failed to retrieve current file path")?;
+            let intervals = file_changes
+                .changes
+                .get_mut(path)
+                .with_context(|| format!("Failed to retrieve ranges of file path {:?}", path))?;
+            // The first line tells whether the hunk has a body. A body may be cut short, e.g. by
+            // a truncated diff, in which case the line is parsed as usual.
+            let is_body_line = if current.has_body {
+                matches!(line.chars().next(), None | Some(' ' | '+' | '-' | '\\'))
+            } else {
+                matches!(line.chars().next(), Some(' ' | '+' | '-' | '\\'))
+                    && !line.starts_with("+++ ")
+                    && !line.starts_with("--- ")
+            };
+            if is_body_line {
+                current.has_body = true;
+                match line.chars().next() {
+                    Some('+') => {
+                        intervals.push((current.next_line, 1));
+                        current.next_line = current.next_line.saturating_add(1);
+                        current.new_remaining = current.new_remaining.saturating_sub(1);
+                        current.run_has_additions = true;
+                    }
+                    Some('-') => {
+                        current.old_remaining = current.old_remaining.saturating_sub(1);
+                        current.run_has_deletions = true;
+                    }
+                    // E.g. `\ No newline at end of file`
+                    Some('\\') => {}
+                    // A context line, whose trailing space may have been trimmed
+                    _ => {
+                        intervals.extend(current.end_run());
+                        current.next_line = current.next_line.saturating_add(1);
+                        current.old_remaining = current.old_remaining.saturating_sub(1);
+                        current.new_remaining = current.new_remaining.saturating_sub(1);
+                    }
+                }
+                if current.is_finished() {
+                    intervals.extend(current.end_run());
+                    hunk = None;
+                }
+                continue;
+            }
+            if current.has_body {
+                intervals.extend(current.end_run());
+            } else {
+                intervals.push(current.header);
+            }
+            hunk = None;
+        }
+        // In the output of `git log -p` each commit starts with a `commit <SHA>` header, followed
+        // by the metadata and by the indented commit message
+        if line.starts_with("commit ") {
+            curr_file_path = None;
+            earlier_commit_files.extend(commit_files.drain());
+            continue;
+        }
+        // Renamed files may have no `+++` header, if their content is unchanged
+        if let Some(cap) = RENAME_RE.captures(line) {
+            let file_path = normalize_path(&cap["filePath"]);
+            if &cap["side"] == "from" {
+                renamed_from = Some(file_path);
+            } else {
+                file_changes.changes.entry(file_path.clone()).or_default();
+                if let Some(old_path) = renamed_from.take() {
+                    file_changes.insert_alias(old_path, file_path.clone());
+                }
+                curr_file_path = Some(file_path);
+            }
+            continue;
+        }
+        // Binary files have no hunks
+        if let Some(cap) = BINARY_RE.captures(line) {
+            let file_path = normalize_path(
+                cap.name("filePath")
+                    .or_else(|| cap.name("hgFilePath"))
+                    .expect("One of the alternatives matched")
+                    .as_str(),
+            );
+            file_changes
+                .changes
+                .entry(file_path.clone())
+                .or_default()
+                .push(WHOLE_FILE);
+            curr_file_path = Some(file_path);
+            continue;
+        }
+        if let Some(cap) = re.captures(line) {
             if let Some(file_path_match) = cap.name("filePath") {
-                let file_path = file_path_match.as_str().to_string();
-                file_changes.insert(file_path.clone(), vec![]);
+                let file_path = normalize_path(match format {
+                    DiffFormat::Git | DiffFormat::Subversion => file_path_match.as_str(),
+                    // Strip the date that follows the path
+                    DiffFormat::Mercurial => file_path_match.as_str().split('\t').next().unwrap(),
+                });
+                // The same file may be modified by several commits of a patch log. Their changes
+                // are combined, although the lines may have moved between the commits.
+                if earlier_commit_files.contains(&file_path)
+                    && warned_files.insert(file_path.clone())
+                {
+                    warn!(
+                        "{} is modified by several commits, whose line numbers refer to different versions of the file; some diagnostics may be misattributed",
+                        file_path
+                    );
+                }
+                commit_files.insert(file_path.clone());
+                file_changes.changes.entry(file_path.clone()).or_default();
                 curr_file_path = Some(file_path);
             }
             if let Some(lines_from_match) = cap.name("linesFrom") {
@@ -36,21 +423,58 @@ pub fn parse_diff(diff: &str) -> Result<FileChanges> {
                 } else {
                     1
                 };
+                let old_len = if let Some(old_len_match) = cap.name("oldLen") {
+                    old_len_match.as_str().parse::<usize>().with_context(|| {
+                        format!("Failed to parse length of old range (line: {:?})", line)
+                    })?
+                } else {
+                    1
+                };
+                if from.checked_add(len).is_none() {
+                    bail!("Lines range is too large (line: {:?})", line);
+                }
                 let curr_file_path_ref = curr_file_path
                     .as_ref()
                     .with_context(|| "Failed to retrieve current file path")?;
-                file_changes
-                    .get_mut(curr_file_path_ref)
-                    .with_context(|| {
-                        format!(
-                            "Failed to retrieve ranges of file path {:?}",
-                            curr_file_path_ref
-                        )
-                    })?
-                    .push((from, len));
+                if !file_changes.changes.contains_key(curr_file_path_ref) {
+                    bail!(
+                        "Failed to retrieve ranges of file path {:?}",
+                        curr_file_path_ref
+                    );
+                }
+                let new_hunk = Hunk::new(old_len, from, len);
+                if new_hunk.is_finished() {
+                    // E.g. `@@ -0,0 +0,0 @@`, whose body is empty
+                    file_changes
+                        .changes
+                        .get_mut(curr_file_path_ref)
+                        .expect("Checked above")
+                        .push(new_hunk.header);
+                } else {
+                    hunk = Some(new_hunk);
+                }
             }
         }
     }
+    if let Some(mut current) = hunk {
+        let path = curr_file_path
+            .as_ref()
+            .with_context(|| "Failed to retrieve current file path")?;
+        let intervals = file_changes
+            .changes
+            .get_mut(path)
+            .with_context(|| format!("Failed to retrieve ranges of file path {:?}", path))?;
+        if current.has_body {
+            intervals.extend(current.end_run());
+        } else {
+            intervals.push(current.header);
+        }
+    }
+
+    // Hunks are ordered within a diff, but they may be adjacent or come from several commits
+    for intervals in file_changes.changes.values_mut() {
+        merge_overlapping(intervals);
+    }
 
     Ok(file_changes)
 }
@@ -81,6 +505,59 @@ mod tests {
         assert_eq!(&file_changes["src/main.rs"], &[(3, 3), (11, 1), (16, 15)]);
     }
 
+    fn sample_changes() -> FileChanges {
+        vec![
+            ("Cargo.toml".to_string(), vec![(10, 1)]),
+            ("src/main.rs".to_string(), vec![(3, 3), (11, 1), (16, 15)]),
+        ]
+        .into_iter()
+        .collect()
+    }
+
+    #[test]
+    fn test_line_count() {
+        assert_eq!(sample_changes().line_count(), 20);
+        assert_eq!(FileChanges::new().line_count(), 0);
+    }
+
+    #[test]
+    fn test_contains_file() {
+        let file_changes = sample_changes();
+        assert!(file_changes.contains_file("Cargo.toml"));
+        assert!(!file_changes.contains_file("src/lib.rs"));
+    }
+
+    #[test]
+    fn test_intervals_for() {
+        let file_changes = sample_changes();
+        assert_eq!(
+            file_changes.intervals_for("Cargo.toml"),
+            Some(&[(10, 1)][..])
+        );
+        assert_eq!(file_changes.intervals_for("src/lib.rs"), None);
+    }
+
+    #[test]
+    fn test_files() {
+        let mut files: Vec<_> = sample_changes().files().map(String::from).collect();
+        files.sort();
+        assert_eq!(files, &["Cargo.toml", "src/main.rs"]);
+    }
+
+    #[test]
+    fn test_merge() {
+        let other: FileChanges = vec![
+            ("Cargo.toml".to_string(), vec![(11, 2)]),
+            ("src/lib.rs".to_string(), vec![(5, 1)]),
+        ]
+        .into_iter()
+        .collect();
+        let merged = sample_changes().merge(other);
+        assert_eq!(&merged["Cargo.toml"], &[(10, 3)]);
+        assert_eq!(&merged["src/lib.rs"], &[(5, 1)]);
+        assert_eq!(merged.len(), 3);
+    }
+
     #[test]
     fn test_parse_diff_2() {
         let diff = indoc! {"
@@ -92,4 +569,310 @@ mod tests {
         assert_eq!(file_changes.len(), 1);
         assert_eq!(&file_changes["prusti-viper/src/encoder/mir_encoder/mod.rs"], &[(98, 5)]);
     }
+
+    #[test]
+    fn test_parse_diff_context() {
+        // As returned by the API of GitHub for a pull request, with 3 lines of context
+        let diff = indoc! {"
+            diff --git a/src/lib.rs b/src/lib.rs
+            index 3f2a1b0..8c4d9e2 100644
+            --- a/src/lib.rs
+            +++ b/src/lib.rs
+            @@ -10,7 +10,8 @@ pub fn parse(input: &str) -> Result<Ast> {
+                 let mut parser = Parser::new(input);
+                 let ast = parser.parse()?;
+                 parser.expect_eof()?;
+            -    Ok(ast)
+            +    let ast = ast.simplify();
+            +    Ok(ast)
+                 }
+             
+             fn helper() {}
+            @@ -40,7 +41,6 @@ impl Parser {
+                 fn next(&mut self) -> Option<Token> {
+                     self.skip_whitespace();
+                     let token = self.peek();
+            -        self.trace(&token);
+                     self.advance();
+                     token
+                 }
+            diff --git a/src/new.rs b/src/new.rs
+            new file mode 100644
+            index 0000000..4d5e6f7
+            --- /dev/null
+            +++ b/src/new.rs
+            @@ -0,0 +1,3 @@
+            +pub fn new() -> u32 {
+            +    42
+            +}
+        "};
+        let file_changes = parse_diff(diff).unwrap();
+        assert_eq!(file_changes.len(), 2);
+        // Only the added lines, and the position of the deleted line after line 43
+        assert_eq!(&file_changes["src/lib.rs"], &[(13, 2), (43, 0)]);
+        assert_eq!(&file_changes["src/new.rs"], &[(1, 3)]);
+    }
+
+    #[test]
+    fn test_parse_diff_log() {
+        let diff = indoc! {"
+            commit 52f1e0c6a1d3b8e54f1c7f3e0b6f0c1f2a3b4c5d
+            Author: Jane Doe <jane@example.com>
+            Date:   Wed Oct 14 09:12:03 2026 +0200
+
+                Use the new API
+
+                +++ b/not/a/file.rs
+
+            diff --git a/src/lib.rs b/src/lib.rs
+            index 0a1b2c3..4d5e6f7 100644
+            --- a/src/lib.rs
+            +++ b/src/lib.rs
+            @@ -20 +20 @@
+            -    let x = 1;
+            +    let x = 2;
+
+            commit 0c9d8e7f6a5b4c3d2e1f0a9b8c7d6e5f4a3b2c1d
+            Author: Jane Doe <jane@example.com>
+            Date:   Tue Oct 13 18:40:55 2026 +0200
+
+                Add imports
+
+            diff --git a/src/lib.rs b/src/lib.rs
+            index 9f8e7d6..0a1b2c3 100644
+            --- a/src/lib.rs
+            +++ b/src/lib.rs
+            @@ -3,0 +4,2 @@
+            +use std::fs;
+            +use std::io;
+            diff --git a/src/main.rs b/src/main.rs
+            index 1a2b3c4..5d6e7f8 100644
+            --- a/src/main.rs
+            +++ b/src/main.rs
+            @@ -7 +7,2 @@
+            -    run();
+            +    init();
+            +    run();
+        "};
+        let file_changes = parse_diff(diff).unwrap();
+        assert_eq!(file_changes.len(), 2);
+        assert_eq!(&file_changes["src/lib.rs"], &[(4, 2), (20, 1)]);
+        assert_eq!(&file_changes["src/main.rs"], &[(7, 2)]);
+    }
+
+    #[test]
+    fn test_parse_diff_log_context() {
+        // The default output of `git log -p`, with 3 lines of context
+        let diff = indoc! {"
+            commit 52f1e0c6a1d3b8e54f1c7f3e0b6f0c1f2a3b4c5d
+            Author: Jane Doe <jane@example.com>
+            Date:   Wed Oct 14 09:12:03 2026 +0200
+
+                Validate the input
+
+            diff --git a/src/lib.rs b/src/lib.rs
+            index 0a1b2c3..4d5e6f7 100644
+            --- a/src/lib.rs
+            +++ b/src/lib.rs
+            @@ -18,6 +18,9 @@ use std::io;
+             /// Process the input, if there is any.
+             pub fn run(input: &str) -> io::Result<()> {
+                 let input = input.trim();
+            +    if input.is_empty() {
+            +        return Ok(());
+            +    }
+                 process(input)?;
+                 flush()
+             }
+
+            commit 0c9d8e7f6a5b4c3d2e1f0a9b8c7d6e5f4a3b2c1d
+            Author: Jane Doe <jane@example.com>
+            Date:   Tue Oct 13 18:40:55 2026 +0200
+
+                Remove the debug output
+
+            diff --git a/src/main.rs b/src/main.rs
+            index 1a2b3c4..5d6e7f8 100644
+            --- a/src/main.rs
+            +++ b/src/main.rs
+            @@ -4,7 +4,6 @@ fn main() {
+                 let args = parse_args();
+                 let input = read_input(&args);
+                 run(&input).unwrap();
+            -    eprintln!(\"done\");
+             }
+             
+             fn parse_args() -> Args {
+        "};
+        let file_changes = parse_diff(diff).unwrap();
+        assert_eq!(file_changes.len(), 2);
+        assert_eq!(&file_changes["src/lib.rs"], &[(21, 3)]);
+        assert_eq!(&file_changes["src/main.rs"], &[(6, 0)]);
+    }
+
+    #[test]
+    fn test_parse_diff_rename() {
+        let diff = indoc! {"
+            diff --git a/src/util.rs b/src/helpers.rs
+            similarity index 92%
+            rename from src/util.rs
+            rename to src/helpers.rs
+            index 0a1b2c3..4d5e6f7 100644
+            --- a/src/util.rs
+            +++ b/src/helpers.rs
+            @@ -12 +12,2 @@
+            -    a();
+            +    b();
+            +    c();
+            diff --git a/README b/README.md
+            similarity index 100%
+            rename from README
+            rename to README.md
+        "};
+        let file_changes = parse_diff(diff).unwrap();
+        assert_eq!(file_changes.len(), 2);
+        assert_eq!(&file_changes["src/helpers.rs"], &[(12, 2)]);
+        assert_eq!(&file_changes["src/util.rs"], &[(12, 2)]);
+        assert_eq!(
+            file_changes.renamed_to("src/util.rs"),
+            Some("src/helpers.rs")
+        );
+        assert_eq!(&file_changes["README.md"], &[]);
+        assert!(file_changes.contains_file("README"));
+    }
+
+    #[test]
+    fn test_parse_diff_binary() {
+        let diff = indoc! {"
+            diff --git a/assets/logo.png b/assets/logo.png
+            index 0a1b2c3..4d5e6f7 100644
+            Binary files a/assets/logo.png and b/assets/logo.png differ
+            diff --git a/assets/new.png b/assets/new.png
+            new file mode 100644
+            index 0000000..4d5e6f7
+            Binary files /dev/null and b/assets/new.png differ
+            diff --git a/assets/old.png b/assets/old.png
+            deleted file mode 100644
+            index 0a1b2c3..0000000
+            Binary files a/assets/old.png and /dev/null differ
+            diff --git a/src/lib.rs b/src/lib.rs
+            index 0a1b2c3..4d5e6f7 100644
+            --- a/src/lib.rs
+            +++ b/src/lib.rs
+            @@ -20 +20 @@
+            -    let x = 1;
+            +    let x = 2;
+        "};
+        let file_changes = parse_diff(diff).unwrap();
+        assert_eq!(file_changes.len(), 3);
+        assert_eq!(&file_changes["assets/logo.png"], &[WHOLE_FILE]);
+        assert_eq!(&file_changes["assets/new.png"], &[WHOLE_FILE]);
+        assert_eq!(&file_changes["src/lib.rs"], &[(20, 1)]);
+        assert!(crate::intervals::intersect_intervals(
+            1000,
+            1000,
+            &file_changes["assets/logo.png"]
+        ));
+        assert!(crate::intervals::intersect_intervals(
+            usize::MAX - 1,
+            usize::MAX,
+            &file_changes["assets/logo.png"]
+        ));
+        // The binary files are not counted, unlike their combination with other changes
+        assert_eq!(file_changes.line_count(), 1);
+        let merged = file_changes.merge(
+            vec![("assets/logo.png".to_string(), vec![(5, 1)])]
+                .into_iter()
+                .collect(),
+        );
+        assert_eq!(&merged["assets/logo.png"], &[WHOLE_FILE]);
+    }
+
+    #[test]
+    fn test_parse_diff_malformed() {
+        assert!(parse_diff("@@ -1 +2 @@").is_err());
+        assert!(parse_diff("+++ b/src/lib.rs\n@@ -1 +18446744073709551615,2 @@").is_err());
+    }
+
+    #[test]
+    fn test_parse_diff_windows_paths() {
+        let diff = indoc! {r#"
+            diff --git a/src\lib.rs b/src\lib.rs
+            --- a/src\lib.rs
+            +++ b/src\lib.rs
+            @@ -10,0 +11,2 @@
+            +a
+            +b
+        "#};
+        let file_changes = parse_diff(diff).unwrap();
+        assert_eq!(&file_changes["src/lib.rs"], &[(11, 2)]);
+
+        assert_eq!(normalize_path("./src\\bin/./main.rs"), "src/bin/main.rs");
+        assert_eq!(
+            normalize_path("/tmp/project/src/lib.rs"),
+            "/tmp/project/src/lib.rs"
+        );
+        assert_eq!(
+            normalize_path("C:\\project\\src\\lib.rs"),
+            "C:/project/src/lib.rs"
+        );
+    }
+
+    #[test]
+    fn test_parse_diff_hg() {
+        let diff = indoc! {"
+            diff -r 9117c6561b0b src/lib.rs
+            --- a/src/lib.rs\tThu Oct 08 14:47:19 2026 +0200
+            +++ b/src/lib.rs\tWed Oct 14 09:12:03 2026 +0200
+            @@ -3,0 +4,2 @@
+            +use std::fs;
+            +use std::io;
+            @@ -20 +22 @@
+            -    let x = 1;
+            +    let x = 2;
+            diff -r 9117c6561b0b assets/logo.png
+            Binary file assets/logo.png has changed
+            diff -r 9117c6561b0b src/main.rs
+            --- a/src/main.rs\tThu Oct 08 14:47:19 2026 +0200
+            +++ b/src/main.rs\tWed Oct 14 09:12:03 2026 +0200
+            @@ -7,3 +7,0 @@
+            -    println!(\"a\");
+            -    println!(\"b\");
+            -    println!(\"c\");
+        "};
+        let file_changes = parse_diff(diff).unwrap();
+        assert_eq!(file_changes.len(), 3);
+        assert_eq!(&file_changes["src/lib.rs"], &[(4, 2), (22, 1)]);
+        assert_eq!(&file_changes["assets/logo.png"], &[WHOLE_FILE]);
+        assert_eq!(&file_changes["src/main.rs"], &[(7, 0)]);
+    }
+
+    #[test]
+    fn test_parse_diff_svn() {
+        let diff = indoc! {"
+            Index: src/lib.rs
+            ===================================================================
+            --- src/lib.rs\t(revision 1042)
+            +++ src/lib.rs\t(working copy)
+            @@ -3,0 +4,2 @@
+            +use std::fs;
+            +use std::io;
+            @@ -20 +22 @@
+            -    let x = 1;
+            +    let x = 2;
+            Index: README.md
+            ===================================================================
+            --- README.md\t(revision 1042)
+            +++ README.md\t(working copy)
+            @@ -1 +1,3 @@
+            -# Title
+            +# New title
+            +
+            +Description.
+        "};
+        let file_changes = parse_diff(diff).unwrap();
+        assert_eq!(file_changes.len(), 2);
+        assert_eq!(&file_changes["src/lib.rs"], &[(4, 2), (22, 1)]);
+        assert_eq!(&file_changes["README.md"], &[(1, 3)]);
+    }
 }
@@ -0,0 +1,142 @@
+use std::error::Error;
+use std::fmt;
+use std::process::ExitStatus;
+
+/// Exit status code of the other failures, e.g. when the output cannot be written.
+pub const FAILURE_EXIT_CODE: i32 = 1;
+
+/// Exit status code of the invalid arguments and configurations.
+pub const CONFIG_EXIT_CODE: i32 = 2;
+
+/// Exit status code used when the diff cannot be obtained, e.g. because `git diff` fails.
+pub const DIFF_EXIT_CODE: i32 = 3;
+
+/// Exit status code used when the subprocess, e.g. cargo, cannot be run or fails without
+/// reporting compilation errors.
+pub const SUBPROCESS_EXIT_CODE: i32 = 4;
+
+/// Exit status code used when diagnostics are reported in the diff.
+pub const DIAGNOSTICS_EXIT_CODE: i32 = 5;
+
+/// Exit status code used when the subprocess is killed by `--timeout`, as the `timeout` command.
+pub const TIMEOUT_EXIT_CODE: i32 = 124;
+
+/// An error of the binaries, whose kind determines the exit status code. The errors that wrap
+/// another error are displayed as the error that they wrap.
+#[derive(Debug)]
+pub enum AppError {
+    /// The arguments or the configuration are invalid.
+    Config(anyhow::Error),
+    /// The diff cannot be obtained.
+    Diff(anyhow::Error),
+    /// The diff tool, e.g. `git diff`, terminated with the given unsuccessful status.
+    GitDiffFailed(ExitStatus),
+    /// The subprocess cannot be run, or fails.
+    Subprocess(anyhow::Error),
+    /// The subprocess terminated with the given unsuccessful status, other than the one of a
+    /// failed compilation.
+    SubprocessFailed(ExitStatus),
+    /// The subprocess was killed after running for the given number of seconds.
+    Timeout { seconds: u64 },
+    /// A line of the diagnostics is not a JSON diagnostic, for the given reason.
+    ParseError(String),
+    /// Diagnostics are reported.
+    DiagnosticsFound(anyhow::Error),
+}
+
+impl AppError {
+    /// Return the exit status code of the error.
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            AppError::Config(_) => CONFIG_EXIT_CODE,
+            AppError::Diff(_) | AppError::GitDiffFailed(_) => DIFF_EXIT_CODE,
+            AppError::Subprocess(_) | AppError::SubprocessFailed(_) => SUBPROCESS_EXIT_CODE,
+            AppError::Timeout { .. } => TIMEOUT_EXIT_CODE,
+            AppError::ParseError(_) => FAILURE_EXIT_CODE,
+            AppError::DiagnosticsFound(_) => DIAGNOSTICS_EXIT_CODE,
+        }
+    }
+
+    fn inner(&self) -> Option<&anyhow::Error> {
+        match self {
+            AppError::Config(err)
+            | AppError::Diff(err)
+            | AppError::Subprocess(err)
+            | AppError::DiagnosticsFound(err) => Some(err),
+            AppError::GitDiffFailed(_)
+            | AppError::SubprocessFailed(_)
+            | AppError::Timeout { .. }
+            | AppError::ParseError(_) => None,
+        }
+    }
+}
+
+impl fmt::Display for AppError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AppError::GitDiffFailed(status) => write!(
+                f,
+                "The diff tool terminated with exit status {}",
+                status.code().unwrap_or(-1)
+            ),
+            AppError::SubprocessFailed(status) => write!(
+                f,
+                "Subprocess terminated with exit code {}",
+                status.code().unwrap_or(-1)
+            ),
+            AppError::Timeout { seconds } => {
+                write!(f, "Subprocess timed out after {} seconds", seconds)
+            }
+            AppError::ParseError(reason) => write!(f, "Invalid JSON diagnostic: {}", reason),
+            _ => fmt::Display::fmt(self.inner().expect("Wraps an error"), f),
+        }
+    }
+}
+
+impl Error for AppError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        self.inner().and_then(|err| err.source())
+    }
+}
+
+/// Return the exit status code of an error returned by [`build_app`](crate::build_app):
+/// the code of its [`AppError`], if there is any, and [`FAILURE_EXIT_CODE`] otherwise.
+pub fn exit_code(err: &anyhow::Error) -> i32 {
+    err.chain()
+        .find_map(|err| err.downcast_ref::<AppError>())
+        .map_or(FAILURE_EXIT_CODE, AppError::exit_code)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::{anyhow, Context};
+
+    #[test]
+    fn test_exit_code() {
+        assert_eq!(exit_code(&anyhow!("Failed to write the output")), 1);
+        let err = anyhow::Error::from(AppError::Diff(
+            anyhow!("exit status 128").context("Failed to run `git diff`"),
+        ));
+        assert_eq!(exit_code(&err), DIFF_EXIT_CODE);
+        assert_eq!(
+            format!("{:#}", err),
+            "Failed to run `git diff`: exit status 128"
+        );
+        let err = Err::<(), _>(AppError::Config(anyhow!("Invalid option")))
+            .context("Failed to parse the arguments")
+            .unwrap_err();
+        assert_eq!(exit_code(&err), CONFIG_EXIT_CODE);
+        let err = anyhow::Error::from(AppError::Timeout { seconds: 60 });
+        assert_eq!(exit_code(&err), TIMEOUT_EXIT_CODE);
+        assert_eq!(err.to_string(), "Subprocess timed out after 60 seconds");
+        let err = Err::<(), _>(AppError::ParseError("expected value".to_string()))
+            .context("Failed to parse JSON from standard input: \"foo\"")
+            .unwrap_err();
+        assert_eq!(exit_code(&err), FAILURE_EXIT_CODE);
+        assert_eq!(
+            format!("{:#}", err),
+            "Failed to parse JSON from standard input: \"foo\": Invalid JSON diagnostic: expected value"
+        );
+    }
+}
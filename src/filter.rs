@@ -0,0 +1,297 @@
+use crate::diagnostics::{doctest_location, Diagnostic, Level, Message, Span};
+use crate::diff::{normalize_path, FileChanges};
+use crate::intervals::{expand_intervals, intersect_intervals};
+use anyhow::{Context, Result};
+use glob::Pattern;
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, PoisonError};
+
+/// Decide which diagnostics to report, given the lines changed by a diff.
+#[derive(Clone, Default, Debug)]
+pub struct DiagnosticFilter {
+    path_base: Option<PathBuf>,
+    /// The canonical paths of the spans, which are costly to compute, by path.
+    canonical_paths: Arc<Mutex<HashMap<String, String>>>,
+    /// If provided, only the diagnostics of these levels are reported.
+    levels: Option<Vec<Level>>,
+    allowed_lints: Vec<String>,
+    denied_lints: Vec<String>,
+    included_files: Vec<Pattern>,
+    excluded_files: Vec<Pattern>,
+    context_lines: usize,
+    /// Also consider the spans of the children messages when checking the changed lines.
+    follow_children: bool,
+    /// Report the diagnostics that are not related to the changed lines, instead of the ones that
+    /// are.
+    invert: bool,
+    /// The target triple, whose components are removed from the paths of the spans.
+    target: Option<String>,
+}
+
+impl DiagnosticFilter {
+    /// Create a filter that reports all the diagnostics related to the changed lines.
+    pub fn new() -> Self {
+        DiagnosticFilter::default()
+    }
+
+    /// Canonicalize the paths of the spans relative to `path_base` before looking them up in the
+    /// changes, which must then use absolute paths. Canonical paths have no symlinks, so that a
+    /// file can be reached through any of them.
+    pub fn with_path_base(mut self, path_base: impl Into<PathBuf>) -> Self {
+        self.path_base = Some(path_base.into());
+        self
+    }
+
+    /// Only report the diagnostics of the given level, or of the other levels passed to this
+    /// method. By default, the diagnostics of all levels are reported.
+    pub fn with_level(mut self, level: Level) -> Self {
+        self.levels.get_or_insert_with(Vec::new).push(level);
+        self
+    }
+
+    /// Only report the diagnostics in the files that match one of the included glob patterns.
+    pub fn with_file_include_glob(mut self, pattern: &str) -> Result<Self> {
+        self.included_files.push(parse_file_pattern(pattern)?);
+        Ok(self)
+    }
+
+    /// Never report the diagnostics in the files that match the glob pattern.
+    pub fn with_file_exclude_glob(mut self, pattern: &str) -> Result<Self> {
+        self.excluded_files.push(parse_file_pattern(pattern)?);
+        Ok(self)
+    }
+
+    /// Also report the diagnostics up to `n` lines away from the changed lines.
+    pub fn with_context_lines(mut self, n: usize) -> Self {
+        self.context_lines = n;
+        self
+    }
+
+    /// Also report the diagnostics whose children messages (e.g. notes) point to the changed
+    /// lines, even if the diagnostic itself is outside of them.
+    pub fn with_follow_children(mut self, follow_children: bool) -> Self {
+        self.follow_children = follow_children;
+        self
+    }
+
+    /// Only report the diagnostics that are *not* related to the changed lines.
+    pub fn with_invert(mut self, invert: bool) -> Self {
+        self.invert = invert;
+        self
+    }
+
+    /// Remove the components equal to the target triple from the paths of the spans, such as the
+    /// ones of the files generated in `target/<triple>/debug/build`, before looking them up in the
+    /// changes.
+    pub fn with_target(mut self, target: &str) -> Self {
+        self.target = Some(target.to_string());
+        self
+    }
+
+    /// Never report the given lints (e.g. `clippy::too_many_arguments`, or just
+    /// `too_many_arguments`).
+    pub fn with_allowed_lints(mut self, lints: &[&str]) -> Self {
+        self.allowed_lints
+            .extend(lints.iter().map(|lint| lint.to_string()));
+        self
+    }
+
+    /// Always report the given lints, even outside of the changed lines.
+    pub fn with_denied_lints(mut self, lints: &[&str]) -> Self {
+        self.denied_lints
+            .extend(lints.iter().map(|lint| lint.to_string()));
+        self
+    }
+
+    /// Return `false` iff the message is not related to changed lines (or is, if inverted), if its
+    /// level is not one of the `levels`, if its lint is allowed, or if its file is not included or
+    /// is excluded. Internal compiler errors are always related. The messages whose lint is denied
+    /// are always reported.
+    pub fn apply(&self, diagnostic: &Diagnostic, file_changes: &FileChanges) -> bool {
+        if let Some(ref message) = diagnostic.message {
+            if let Some(code) = diagnostic.lint_code() {
+                if self
+                    .denied_lints
+                    .iter()
+                    .any(|lint| lint_matches(lint, code))
+                {
+                    return true;
+                }
+                if self
+                    .allowed_lints
+                    .iter()
+                    .any(|lint| lint_matches(lint, code))
+                {
+                    return false;
+                }
+            }
+            if !matches!(message.level, Level::Ice) {
+                let mut spans = Vec::new();
+                collect_spans(message, self.follow_children, &mut spans);
+                // Rustdoc may point to the HTML files that it generated, which are never in a diff
+                spans.retain(|span| !is_generated_html(&span.file_name));
+                let has_spans = !spans.is_empty();
+                let mut intersects_changes = false;
+                for span in spans {
+                    let (file_name, line_start, line_end) = match doctest_location(&span.file_name)
+                    {
+                        // The lines of a doctest are relative to the line of its code block
+                        Some((file_name, line)) => {
+                            (file_name, line + span.line_start, line + span.line_end)
+                        }
+                        None => (span.file_name.as_str(), span.line_start, span.line_end),
+                    };
+                    let file_name = self.span_path(file_name);
+                    // A test may fail because of changes far from where it panics
+                    if diagnostic.is_test_failure() && file_changes.contains_file(&file_name) {
+                        intersects_changes = true;
+                        break;
+                    }
+                    if let Some(file_changes) = file_changes.intervals_for(&file_name) {
+                        let file_changes = if self.context_lines > 0 {
+                            Cow::Owned(expand_intervals(file_changes, self.context_lines))
+                        } else {
+                            Cow::Borrowed(file_changes)
+                        };
+                        if intersect_intervals(line_start, line_end, &file_changes) {
+                            intersects_changes = true;
+                            break;
+                        }
+                    }
+                }
+                // Messages without spans are related to no line, changed or not
+                if !has_spans || intersects_changes == self.invert {
+                    return false;
+                }
+            }
+            if let Some(primary_span) = diagnostic.primary_span() {
+                if !self.included_files.is_empty() || !self.excluded_files.is_empty() {
+                    let file_name = self.relative_path(&primary_span.file_name);
+                    if !self.included_files.is_empty()
+                        && !self
+                            .included_files
+                            .iter()
+                            .any(|pattern| pattern.matches(&file_name))
+                    {
+                        return false;
+                    }
+                    if self
+                        .excluded_files
+                        .iter()
+                        .any(|pattern| pattern.matches(&file_name))
+                    {
+                        return false;
+                    }
+                }
+            }
+            if let Some(ref levels) = self.levels {
+                if !levels.contains(&message.level) {
+                    return false;
+                }
+            }
+        }
+        true
+    }
+}
+
+impl DiagnosticFilter {
+    /// Return the path of a span as it is looked up in the changes: normalized, or canonical if
+    /// there is a path base, and without the components of the target triple.
+    fn span_path(&self, file_name: &str) -> String {
+        let file_name = match self.path_base {
+            Some(ref base) => self.canonical_path(base, file_name),
+            None => normalize_path(file_name),
+        };
+        match self.target {
+            Some(ref target) => strip_component(&file_name, target),
+            None => file_name,
+        }
+    }
+
+    /// Return the path of a span relative to the path base, or to the current directory, as
+    /// matched by the file patterns. Paths outside of it are returned absolute.
+    fn relative_path(&self, file_name: &str) -> String {
+        let path = self.span_path(file_name);
+        if !Path::new(&path).is_absolute() {
+            return path;
+        }
+        let base = match self.path_base {
+            Some(ref base) => self.canonical_path(base, "."),
+            None => match env::current_dir() {
+                Ok(dir) => normalize_path(&dir.to_string_lossy()),
+                Err(_) => return path,
+            },
+        };
+        match path
+            .strip_prefix(&base)
+            .and_then(|relative| relative.strip_prefix('/'))
+        {
+            Some(relative) => relative.to_string(),
+            None => path,
+        }
+    }
+
+    /// Return the canonical path of a span, reading the file system only once per path.
+    fn canonical_path(&self, base: &Path, file_name: &str) -> String {
+        let mut canonical_paths = self
+            .canonical_paths
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        canonical_paths
+            .entry(file_name.to_string())
+            .or_insert_with(|| canonicalize_path(base, file_name))
+            .clone()
+    }
+}
+
+/// Collect the spans of the message and, if `follow_children` is set, of its children.
+fn collect_spans<'a>(message: &'a Message, follow_children: bool, spans: &mut Vec<&'a Span>) {
+    spans.extend(&message.spans);
+    if follow_children {
+        for child in &message.children {
+            collect_spans(child, follow_children, spans);
+        }
+    }
+}
+
+/// Return `true` iff the file is one of the HTML pages generated by rustdoc.
+fn is_generated_html(file_name: &str) -> bool {
+    Path::new(file_name)
+        .extension()
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("html"))
+}
+
+fn parse_file_pattern(pattern: &str) -> Result<Pattern> {
+    Pattern::new(pattern).with_context(|| format!("Invalid file pattern {:?}", pattern))
+}
+
+/// Return `true` iff the lint code matches the given lint name, ignoring the case. A lint name
+/// without a tool prefix (e.g. `foo`) matches the lints of any tool (e.g. `clippy::foo`).
+fn lint_matches(lint: &str, code: &str) -> bool {
+    let lint = lint.to_ascii_lowercase();
+    let code = code.to_ascii_lowercase();
+    if lint.contains("::") {
+        code == lint
+    } else {
+        code.rsplit("::").next() == Some(lint.as_str())
+    }
+}
+
+/// Remove the components equal to `component` from a normalized path.
+fn strip_component(path: &str, component: &str) -> String {
+    path.split('/')
+        .filter(|c| *c != component)
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// Return the canonical, absolute form of `path`, resolving it from `base` if it is relative.
+/// Paths that do not exist are only made absolute.
+pub(crate) fn canonicalize_path(base: &Path, path: &str) -> String {
+    let path = base.join(normalize_path(path));
+    normalize_path(&fs::canonicalize(&path).unwrap_or(path).to_string_lossy())
+}
@@ -0,0 +1,304 @@
+use crate::cli::{diff_app, is_git_ref};
+use anyhow::{bail, Context, Result};
+use clap::{crate_description, crate_version, value_t, App, AppSettings, Arg, Shell, SubCommand};
+use std::env;
+use std::fs;
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::process::Command;
+
+/// The binaries of the crate, whose shell completions can be generated.
+const BINARIES: &[&str] = &[
+    "cargo-clippy-diff",
+    "cargo-check-diff",
+    "cargo-test-diff",
+    "cargo-doc-diff",
+    "cargo-rustc-diff",
+    "filter-by-diff",
+    "cargo-diff-tools",
+];
+
+/// The options whose values are paths, to be completed as such.
+const PATH_OPTIONS: &[&str] = &[
+    "config",
+    "output-file",
+    "diff-file",
+    "diagnostics-file",
+    "baseline-file",
+    "save-baseline",
+    "cache-dir",
+    "git-binary",
+    "cargo-binary",
+    "git-dir",
+    "work-tree",
+];
+
+/// A `pre-commit` git hook that reports the clippy diagnostics of the staged changes.
+const PRE_COMMIT_HOOK: &str = r#"#!/bin/sh
+# Generated by `cargo-diff-tools generate-hook`.
+# Abort the commit if clippy reports diagnostics in the staged changes.
+
+if ! command -v cargo-clippy-diff >/dev/null 2>&1; then
+    echo "error: cargo-clippy-diff was not found in PATH; install it with \`cargo install cargo-diff-tools\`" >&2
+    exit 1
+fi
+
+exec cargo-clippy-diff --staged --output=rendered
+"#;
+
+/// A GitHub Actions workflow that annotates pull requests with the clippy diagnostics of their
+/// changes. `{base_ref}` and `{toolchain}` are placeholders.
+const GITHUB_WORKFLOW: &str = r#"# Generated by `cargo-diff-tools generate-github-action`.
+name: Diff lint
+
+on:
+  pull_request:
+
+jobs:
+  clippy-diff:
+    runs-on: ubuntu-latest
+    permissions:
+      contents: read
+      pull-requests: write
+    steps:
+      - uses: actions/checkout@v4
+        with:
+          # The base branch is needed to compute the diff
+          fetch-depth: 0
+      - uses: dtolnay/rust-toolchain@master
+        with:
+          toolchain: {toolchain}
+          components: clippy
+      - uses: actions/cache@v4
+        with:
+          path: |
+            ~/.cargo/registry
+            ~/.cargo/git
+            target
+          key: ${{ runner.os }}-cargo-${{ hashFiles('**/Cargo.lock') }}
+      - run: cargo install cargo-diff-tools
+      - run: cargo-clippy-diff --base-ref {base_ref} -o github
+"#;
+
+/// Return the content of the `pre-commit` git hook.
+pub fn pre_commit_hook() -> &'static str {
+    PRE_COMMIT_HOOK
+}
+
+/// Return the content of the GitHub Actions workflow, which diffs the pull requests against
+/// `base_ref` and installs the given Rust toolchain.
+pub fn github_workflow(base_ref: &str, toolchain: &str) -> String {
+    GITHUB_WORKFLOW
+        .replace("{base_ref}", base_ref)
+        .replace("{toolchain}", toolchain)
+}
+
+/// Write the `pre-commit` hook in the hooks directory of the current git repository, and make it
+/// executable. Return the path of the hook.
+fn install_pre_commit_hook() -> Result<PathBuf> {
+    let output = Command::new("git")
+        .args(["rev-parse", "--git-path", "hooks"])
+        .output()
+        .with_context(|| "Failed to run `git rev-parse`")?;
+    if !output.status.success() {
+        bail!(
+            "Failed to find the git hooks directory: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    let hooks_dir = PathBuf::from(String::from_utf8_lossy(&output.stdout).trim());
+    let hook_path = hooks_dir.join("pre-commit");
+    if hook_path.exists() {
+        bail!(
+            "The hook {:?} already exists; remove it to install a new one",
+            hook_path
+        );
+    }
+    fs::create_dir_all(&hooks_dir)
+        .with_context(|| format!("Failed to create directory {:?}", hooks_dir))?;
+    fs::write(&hook_path, PRE_COMMIT_HOOK)
+        .with_context(|| format!("Failed to write hook {:?}", hook_path))?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(&hook_path, fs::Permissions::from_mode(0o755))
+            .with_context(|| format!("Failed to make hook {:?} executable", hook_path))?;
+    }
+    Ok(hook_path)
+}
+
+/// Parse the arguments of the `cargo-diff-tools` binary, which generates the files needed to
+/// integrate the other binaries in a project.
+pub fn build_generate_app(binary_name: &str) -> Result<()> {
+    let mut args: Vec<String> = env::args().collect();
+    // When run as `cargo diff-tools`, cargo passes the name of the subcommand as first argument
+    if args.get(1).map(String::as_str) == Some("diff-tools") {
+        args.remove(1);
+    }
+
+    let matches = generate_app(binary_name).get_matches_from(&args);
+
+    if let Some(matches) = matches.subcommand_matches("generate-hook") {
+        if matches.is_present("install") {
+            let hook_path = install_pre_commit_hook()?;
+            eprintln!("Installed the pre-commit hook {:?}", hook_path);
+        } else {
+            print!("{}", pre_commit_hook());
+        }
+    } else if let Some(matches) = matches.subcommand_matches("generate-github-action") {
+        print!(
+            "{}",
+            github_workflow(
+                matches.value_of("base-ref").expect("Has a default value"),
+                matches
+                    .value_of("rust-toolchain")
+                    .expect("Has a default value"),
+            )
+        );
+    } else if let Some(matches) = matches.subcommand_matches("completions") {
+        let shell = value_t!(matches, "shell", Shell).unwrap_or_else(|err| err.exit());
+        let bin = matches.value_of("bin").expect("Has a default value");
+        let mut app = if bin == binary_name {
+            generate_app(bin)
+        } else {
+            diff_app(bin)
+        };
+        let mut completions = Vec::new();
+        app.gen_completions_to(bin, shell, &mut completions);
+        io::stdout()
+            .write_all(complete_paths(&String::from_utf8_lossy(&completions), shell).as_bytes())
+            .with_context(|| "Failed to write the completions")?;
+    }
+    Ok(())
+}
+
+/// Make the completions of `PATH_OPTIONS` list the files, which clap only does for bash.
+fn complete_paths(completions: &str, shell: Shell) -> String {
+    completions
+        .lines()
+        .map(|line| {
+            let option = PATH_OPTIONS.iter().find(|option| match shell {
+                Shell::Zsh => line.contains(&format!("--{}=[", option)),
+                Shell::Fish => line.contains(&format!(" -l {} ", option)),
+                _ => false,
+            });
+            match (option, shell) {
+                // E.g. `'--diff-file=[Read the diff]' \` becomes `'--diff-file=[Read the diff]:PATH:_files' \`
+                (Some(_), Shell::Zsh) => match line.rfind("]'") {
+                    Some(pos) => format!("{}]:PATH:_files{}", &line[..pos], &line[pos + 1..]),
+                    None => line.to_string(),
+                },
+                (Some(_), Shell::Fish) => format!("{} -r -F", line),
+                _ => line.to_string(),
+            }
+        })
+        .map(|line| line + "\n")
+        .collect()
+}
+
+/// Return the command-line interface of the `cargo-diff-tools` binary.
+fn generate_app(binary_name: &str) -> App<'static, 'static> {
+    App::new(binary_name)
+        .version(crate_version!())
+        .author(env!("CARGO_PKG_AUTHORS"))
+        .about(crate_description!())
+        .setting(AppSettings::SubcommandRequiredElseHelp)
+        .subcommand(
+            SubCommand::with_name("generate-hook")
+                .about("Print a git `pre-commit` hook that reports the clippy diagnostics of the staged changes")
+                .arg(
+                    Arg::with_name("install")
+                        .long("install")
+                        .help("Write the hook to the hooks directory of the current git repository, instead of printing it"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("generate-github-action")
+                .about("Print a GitHub Actions workflow (e.g. `.github/workflows/diff-lint.yml`) that annotates pull requests with the clippy diagnostics of their changes")
+                .arg(
+                    Arg::with_name("base-ref")
+                        .long("base-ref")
+                        .value_name("REF")
+                        .help("The ref to diff the pull requests against")
+                        .default_value("origin/main")
+                        .validator(is_git_ref),
+                )
+                .arg(
+                    Arg::with_name("rust-toolchain")
+                        .long("rust-toolchain")
+                        .value_name("TOOLCHAIN")
+                        .help("The Rust toolchain to install")
+                        .default_value("stable")
+                        .validator(is_toolchain),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("completions")
+                .about("Print the shell completions of a binary of the crate")
+                .arg(
+                    Arg::with_name("shell")
+                        .value_name("SHELL")
+                        .help("The shell to generate the completions for")
+                        .required(true)
+                        .possible_values(&Shell::variants()),
+                )
+                .arg(
+                    Arg::with_name("bin")
+                        .long("bin")
+                        .value_name("NAME")
+                        .help("The binary to generate the completions for")
+                        .default_value("cargo-clippy-diff")
+                        .possible_values(BINARIES),
+                ),
+        )
+}
+
+/// Reject the toolchain names that could break the YAML of the workflow.
+fn is_toolchain(value: String) -> std::result::Result<(), String> {
+    if !value.is_empty()
+        && value
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '-' | '_'))
+    {
+        Ok(())
+    } else {
+        Err(format!("{:?} is not a valid toolchain name", value))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pre_commit_hook() {
+        let hook = pre_commit_hook();
+        assert!(hook.starts_with("#!/bin/sh\n"));
+        assert!(hook.contains("command -v cargo-clippy-diff"));
+        assert!(hook.contains("exec cargo-clippy-diff --staged"));
+    }
+
+    #[test]
+    fn test_github_workflow() {
+        let workflow = github_workflow("origin/develop", "1.70.0");
+        assert!(workflow.contains("fetch-depth: 0"));
+        assert!(workflow.contains("toolchain: 1.70.0"));
+        assert!(workflow.contains("pull-requests: write"));
+        assert!(workflow.contains("run: cargo-clippy-diff --base-ref origin/develop -o github"));
+        assert!(workflow.contains("key: ${{ runner.os }}-cargo-"));
+    }
+
+    #[test]
+    fn test_complete_paths() {
+        let zsh = "'--diff-file=[Read the diff]' \\\n'--output=[Format]: :(Json)' \\\n";
+        assert_eq!(
+            complete_paths(zsh, Shell::Zsh),
+            "'--diff-file=[Read the diff]:PATH:_files' \\\n'--output=[Format]: :(Json)' \\\n"
+        );
+        let fish = "complete -c filter-by-diff -l diff-file -d 'Read the diff'\n";
+        assert_eq!(
+            complete_paths(fish, Shell::Fish),
+            "complete -c filter-by-diff -l diff-file -d 'Read the diff' -r -F\n"
+        );
+    }
+}
@@ -1,5 +1,11 @@
 /// Return `true` iff `[start, end]` intersects one of the given intervals.
-/// `intervals` is an orderd list of `(interval_start, interval_length)` pairs.
+/// `intervals` is an ordered list of `(interval_start, interval_length)` pairs.
+///
+/// Both bounds of `[start, end]` are inclusive, whereas an interval covers the lines from
+/// `interval_start` (inclusive) to `interval_start + interval_length` (exclusive). So, the span
+/// `[1, 5]` intersects `(5, 1)` but not `(6, 1)`, and `[6, 8]` intersects `(3, 4)` but not
+/// `(3, 3)`. An empty interval `(line, 0)`, i.e. the position of some deleted lines, intersects
+/// the spans that contain `line`.
 pub fn intersect_intervals(start: usize, end: usize, intervals: &[(usize, usize)]) -> bool {
     match intervals.binary_search_by_key(&end, |&(s, _)| s) {
         Ok(_) => {
@@ -15,12 +21,15 @@ pub fn intersect_intervals(start: usize, end: usize, intervals: &[(usize, usize)
                     return true;
                 }
                 debug_assert!(last_start < start);
-                if last_start + last_len > start {
+                // Saturate for the intervals of the files changed entirely, which end at the end of
+                // the address space
+                if last_start.saturating_add(last_len) > start {
                     // An interval starts before `start` and includes it.
                     return true;
                 }
-                // The last interval starts and ends before `start`.
-                debug_assert!(last_start + last_len - 1 < start);
+                // The last interval starts and ends before `start`. Don't subtract 1 from its end,
+                // which would underflow for the deletions at the top of a file, i.e. `(0, 0)`.
+                debug_assert!(last_start.saturating_add(last_len) <= start);
                 false
             } else {
                 // No interval starts before `end`.
@@ -29,3 +38,269 @@ pub fn intersect_intervals(start: usize, end: usize, intervals: &[(usize, usize)
         }
     }
 }
+
+/// Return the parts of the given intervals that intersect `[line_start, line_end]`, as an ordered
+/// list of `(start, len)` pairs. Empty intervals (e.g. the position of deleted lines) are returned
+/// unchanged if they are positioned within `[line_start, line_end]`.
+pub fn intersecting_ranges(
+    line_start: usize,
+    line_end: usize,
+    changes: &[(usize, usize)],
+) -> Vec<(usize, usize)> {
+    changes
+        .iter()
+        .take_while(|&&(start, _)| start <= line_end)
+        .filter_map(|&(start, len)| {
+            if len == 0 {
+                return if start >= line_start {
+                    Some((start, 0))
+                } else {
+                    None
+                };
+            }
+            let overlap_start = start.max(line_start);
+            let overlap_end = (start.saturating_add(len) - 1).min(line_end);
+            if overlap_start <= overlap_end {
+                Some((overlap_start, overlap_end - overlap_start + 1))
+            } else {
+                None
+            }
+        })
+        .collect()
+}
+
+/// Merge two ordered lists of disjoint intervals into one ordered list of disjoint intervals.
+pub fn union_intervals(a: &[(usize, usize)], b: &[(usize, usize)]) -> Vec<(usize, usize)> {
+    let mut intervals = [a, b].concat();
+    merge_overlapping(&mut intervals);
+    intervals
+}
+
+/// Sort the intervals and collapse the ones that overlap or are adjacent. Empty intervals are
+/// only collapsed if they are within another interval, so that they keep marking the position of
+/// deleted lines.
+pub fn merge_overlapping(intervals: &mut Vec<(usize, usize)>) {
+    intervals.sort_unstable();
+    let mut merged: Vec<(usize, usize)> = Vec::with_capacity(intervals.len());
+    for &(start, len) in intervals.iter() {
+        match merged.last_mut() {
+            Some((last_start, last_len))
+                if start < last_start.saturating_add(*last_len)
+                    || (start == last_start.saturating_add(*last_len) && len > 0) =>
+            {
+                *last_len = last_start
+                    .saturating_add(*last_len)
+                    .max(start.saturating_add(len))
+                    - *last_start;
+            }
+            _ => merged.push((start, len)),
+        }
+    }
+    *intervals = merged;
+}
+
+/// Return the intervals of the lines of a file of `total_lines` lines that are not in the given
+/// ordered list of disjoint intervals, e.g. the unchanged lines of a file.
+pub fn complement_intervals(
+    intervals: &[(usize, usize)],
+    total_lines: usize,
+) -> Vec<(usize, usize)> {
+    let mut complement = Vec::with_capacity(intervals.len() + 1);
+    // The first line that is not known to be in an interval
+    let mut next_line = 1;
+    for &(start, len) in intervals {
+        if start > total_lines {
+            break;
+        }
+        if len == 0 {
+            continue;
+        }
+        if start > next_line {
+            complement.push((next_line, start - next_line));
+        }
+        next_line = next_line.max(start.saturating_add(len));
+    }
+    if next_line <= total_lines {
+        complement.push((next_line, total_lines - next_line + 1));
+    }
+    complement
+}
+
+/// Expand each of the given intervals by `context` lines on both sides, merging the intervals
+/// that end up overlapping so that the result is still an ordered list of disjoint intervals.
+pub fn expand_intervals(intervals: &[(usize, usize)], context: usize) -> Vec<(usize, usize)> {
+    let mut expanded: Vec<(usize, usize)> = Vec::with_capacity(intervals.len());
+    for &(start, len) in intervals {
+        let new_start = start.saturating_sub(context).max(1);
+        let new_end = start
+            .saturating_add(len)
+            .saturating_add(context)
+            .max(new_start);
+        match expanded.last_mut() {
+            Some((last_start, last_len)) if last_start.saturating_add(*last_len) >= new_start => {
+                *last_len = last_start.saturating_add(*last_len).max(new_end) - *last_start;
+            }
+            _ => expanded.push((new_start, new_end - new_start)),
+        }
+    }
+    expanded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    /// Generate an ordered list of disjoint, non-empty intervals.
+    fn intervals_strategy() -> impl Strategy<Value = Vec<(usize, usize)>> {
+        prop::collection::vec((0..20usize, 1..20usize), 0..10).prop_map(|gaps_and_lens| {
+            let mut next_line = 1;
+            gaps_and_lens
+                .into_iter()
+                .map(|(gap, len)| {
+                    let start = next_line + gap;
+                    next_line = start + len;
+                    (start, len)
+                })
+                .collect()
+        })
+    }
+
+    /// Generate a `[start, end]` span of lines.
+    fn span_strategy() -> impl Strategy<Value = (usize, usize)> {
+        (1..300usize, 0..30usize).prop_map(|(start, len)| (start, start + len))
+    }
+
+    proptest! {
+        #[test]
+        fn prop_intersect_intervals_like_linear_search(
+            (start, end) in span_strategy(),
+            intervals in intervals_strategy(),
+        ) {
+            let expected = intervals
+                .iter()
+                .any(|&(s, len)| s <= end && start < s + len);
+            prop_assert_eq!(intersect_intervals(start, end, &intervals), expected);
+        }
+
+        #[test]
+        fn prop_intersect_intervals_symmetric(
+            (start, end) in span_strategy(),
+            (other_start, other_end) in span_strategy(),
+        ) {
+            // Swapping the roles of the span and of the interval doesn't change the result
+            prop_assert_eq!(
+                intersect_intervals(start, end, &[(other_start, other_end - other_start + 1)]),
+                intersect_intervals(other_start, other_end, &[(start, end - start + 1)])
+            );
+        }
+
+        #[test]
+        fn prop_intersect_intervals_inside(
+            intervals in intervals_strategy(),
+            index in any::<prop::sample::Index>(),
+            offset in 0..20usize,
+            len in 0..20usize,
+        ) {
+            prop_assume!(!intervals.is_empty());
+            let (s, l) = intervals[index.index(intervals.len())];
+            let start = s + offset % l;
+            let end = (start + len).min(s + l - 1);
+            prop_assert!(intersect_intervals(start, end, &intervals));
+            prop_assert!(intersect_intervals(start, start, &intervals));
+        }
+
+        #[test]
+        fn prop_intersect_intervals_outside(
+            intervals in intervals_strategy(),
+            (start, end) in span_strategy(),
+        ) {
+            // Remove the intervals that intersect the span, leaving it outside of all of them
+            let outside: Vec<_> = intervals
+                .into_iter()
+                .filter(|&(s, len)| s + len <= start || s > end)
+                .collect();
+            prop_assert!(!intersect_intervals(start, end, &outside));
+            prop_assert!(!intersect_intervals(start, start, &outside));
+        }
+
+        #[test]
+        fn prop_intersect_intervals_empty((start, end) in span_strategy()) {
+            prop_assert!(!intersect_intervals(start, end, &[]));
+        }
+    }
+
+    #[test]
+    fn test_intersect_intervals_adjacent() {
+        // The span ends right before the interval
+        assert!(!intersect_intervals(3, 5, &[(6, 2)]));
+        assert!(intersect_intervals(3, 6, &[(6, 2)]));
+        // The span starts right after the interval
+        assert!(!intersect_intervals(6, 8, &[(3, 3)]));
+        assert!(intersect_intervals(5, 8, &[(3, 3)]));
+        // Between two adjacent intervals
+        assert!(!intersect_intervals(4, 4, &[(1, 3), (5, 2)]));
+        assert!(intersect_intervals(4, 5, &[(1, 3), (5, 2)]));
+    }
+
+    #[test]
+    fn test_intersect_intervals_empty() {
+        // Lines deleted at the top of a file, e.g. `@@ -1,2 +0,0 @@`
+        assert!(!intersect_intervals(1, 1, &[(0, 0)]));
+        assert!(!intersect_intervals(5, 10, &[(0, 0), (20, 2)]));
+        assert!(intersect_intervals(7, 7, &[(7, 0)]));
+        assert!(!intersect_intervals(8, 9, &[(7, 0)]));
+    }
+
+    #[test]
+    fn test_intersecting_ranges() {
+        let changes = [(2, 3), (7, 0), (10, 5)];
+        assert_eq!(intersecting_ranges(1, 1, &changes), &[]);
+        assert_eq!(intersecting_ranges(3, 3, &changes), &[(3, 1)]);
+        assert_eq!(
+            intersecting_ranges(4, 12, &changes),
+            &[(4, 1), (7, 0), (10, 3)]
+        );
+        assert_eq!(intersecting_ranges(15, 20, &changes), &[]);
+    }
+
+    #[test]
+    fn test_merge_overlapping() {
+        let mut intervals = vec![(10, 2), (3, 2), (5, 1), (11, 4), (20, 0), (21, 1)];
+        merge_overlapping(&mut intervals);
+        assert_eq!(intervals, &[(3, 3), (10, 5), (20, 0), (21, 1)]);
+
+        let mut intervals = vec![(7, 0), (5, 2), (7, 2), (4, 0)];
+        merge_overlapping(&mut intervals);
+        assert_eq!(intervals, &[(4, 0), (5, 2), (7, 2)]);
+    }
+
+    #[test]
+    fn test_union_intervals() {
+        assert_eq!(
+            union_intervals(&[(1, 2), (10, 1)], &[(2, 3), (12, 1)]),
+            &[(1, 4), (10, 1), (12, 1)]
+        );
+        assert_eq!(union_intervals(&[], &[(4, 1)]), &[(4, 1)]);
+    }
+
+    #[test]
+    fn test_complement_intervals() {
+        assert_eq!(complement_intervals(&[], 10), &[(1, 10)]);
+        assert_eq!(complement_intervals(&[(1, 10)], 10), &[]);
+        assert_eq!(
+            complement_intervals(&[(2, 3), (7, 0), (9, 5)], 10),
+            &[(1, 1), (5, 4)]
+        );
+        assert_eq!(complement_intervals(&[(1, 2)], 0), &[]);
+    }
+
+    #[test]
+    fn test_expand_intervals() {
+        assert_eq!(expand_intervals(&[(2, 1), (10, 3)], 0), &[(2, 1), (10, 3)]);
+        assert_eq!(expand_intervals(&[(2, 1), (10, 3)], 2), &[(1, 4), (8, 7)]);
+        assert_eq!(expand_intervals(&[(2, 1), (6, 1)], 2), &[(1, 8)]);
+        assert!(intersect_intervals(5, 5, &expand_intervals(&[(4, 1)], 1)));
+        assert!(!intersect_intervals(6, 6, &expand_intervals(&[(4, 1)], 1)));
+    }
+}
@@ -1,200 +1,822 @@
-use crate::diagnostics::{Diagnostic, Level};
-use crate::diff::{parse_diff, FileChanges};
-use crate::intervals::intersect_intervals;
-use crate::reporters::{report_diagnostic, OutputKind};
-use anyhow::{bail, Context, Result};
-use clap::{crate_authors, crate_description, crate_version, value_t, App, AppSettings, Arg};
-use std::process::{Command, Stdio};
-use std::{
-    env,
-    io::{self, BufRead, BufReader, Write},
-};
+//! Filter the diagnostics reported by `cargo` and `rustc` to the ones related to the lines
+//! changed by a diff.
+//!
+//! Besides the command-line tools, the crate can be used as a library: parse a diff with
+//! [`parse_diff`] and decide which [`Diagnostic`]s to report with a [`DiagnosticFilter`].
+
+use crate::baseline::Baseline;
+use crate::cache::Cache;
+use crate::reporters::{override_level, strip_colors};
+use crate::sort::group_by_file;
+use anyhow::{Context, Result};
+use glob::Pattern;
+use log::{debug, warn};
+use rayon::prelude::*;
+use std::collections::HashSet;
+use std::fs::File;
+use std::io::{self, BufRead, BufWriter, Write};
+use std::path::PathBuf;
 
+mod baseline;
+mod cache;
+mod cli;
+mod config;
 mod diagnostics;
 mod diff;
+mod error;
+mod filter;
+mod generate;
 mod intervals;
+mod providers;
 mod reporters;
+mod sort;
 
-/// Cargo failed to complete exit status code per:
-/// https://github.com/rust-lang/cargo/blob/master/src/doc/src/commands/cargo.md
-const CARGO_FAILED_EXIT_CODE: i32 = 101;
-
-pub fn build_app(binary_name: &str, subcommand: Option<(&str, &[&str])>) -> Result<()> {
-    // Rip off the arguments to be passed to the subcommand
-    let app_args: Vec<String>;
-    let subcommand_extra_args: Vec<String>;
-    if subcommand.is_none() {
-        app_args = env::args().collect();
-        subcommand_extra_args = vec![];
-    } else {
-        let args: Vec<_> = env::args().collect();
-        if let Some(split_pos) = args.iter().position(|v| v == "--") {
-            app_args = args[0..split_pos].into();
-            if split_pos + 1 == args.len() {
-                subcommand_extra_args = vec![];
-            } else {
-                subcommand_extra_args = args[(split_pos + 1)..].into();
-            }
-        } else {
-            app_args = args;
-            subcommand_extra_args = vec![];
-        }
-    }
-
-    // Parse the argument of this binary
-    let matches = App::new(binary_name)
-        .version(crate_version!())
-        .author(crate_authors!("\n"))
-        .about(crate_description!())
-        .setting(AppSettings::TrailingVarArg)
-        .setting(AppSettings::AllowLeadingHyphen)
-        .arg(
-            Arg::with_name("output")
-                .short("o")
-                .long("output")
-                .value_name("FORMAT")
-                .help("Format of the output")
-                .possible_values(&OutputKind::variants())
-                .case_insensitive(true),
-        )
-        .arg(
-            Arg::with_name("args")
-                .value_name("FORMAT")
-                .help("Additional arguments to pass to `git diff`")
-                .multiple(true),
-        )
-        .get_matches_from(&app_args);
-
-    // Read `git diff` arguments
-    let git_diff_args = matches.values_of("args").unwrap_or_default();
-
-    // Obtain diff
-    let output = Command::new("git")
-        .arg("diff")
-        .arg("--unified=0")
-        .args(git_diff_args)
-        .output()
-        .with_context(|| "Failed to start `git diff`")?;
-
-    if !output.stderr.is_empty() {
-        io::stderr()
-            .write_all(&output.stderr)
-            .with_context(|| "Failed to report the stderr of `git diff`")?;
-    }
-    if !output.status.success() {
-        bail!(
-            "`git diff` terminated with exit status {:?}",
-            output.status.code().unwrap()
-        );
+pub use crate::cli::{build_app, CliApp, CliArgs, DiagnosticsInput, DiffSource};
+pub use crate::diagnostics::{
+    Diagnostic, DiagnosticCode, Level, Message, Span, TEST_FAILED_REASON,
+};
+pub use crate::diff::{parse_diff, DiffTool, FileChanges};
+pub use crate::error::{
+    exit_code, AppError, CONFIG_EXIT_CODE, DIAGNOSTICS_EXIT_CODE, DIFF_EXIT_CODE,
+    FAILURE_EXIT_CODE, SUBPROCESS_EXIT_CODE, TIMEOUT_EXIT_CODE,
+};
+pub use crate::filter::DiagnosticFilter;
+pub use crate::generate::{build_generate_app, github_workflow, pre_commit_hook};
+pub use crate::intervals::{
+    complement_intervals, intersect_intervals, intersecting_ranges, merge_overlapping,
+    union_intervals,
+};
+pub use crate::providers::{DiffProvider, FileDiffProvider, GitDiffProvider, StdinDiffProvider};
+pub use crate::reporters::{
+    CapturingReporter, ColorChoice, DocumentReporter, GitHubReporter, JsonReporter, MultiReporter,
+    OutputKind, RenderedReporter, Reporter, TeamCityReporter,
+};
+pub use crate::sort::SortKey;
+
+/// Options that control which diagnostics are reported, and how.
+#[derive(Clone, Debug)]
+pub struct ProcessOptions {
+    pub filter: DiagnosticFilter,
+    /// Count every warning as a failure, even if it was not reported.
+    pub fail_on_warnings: bool,
+    /// Report the warnings as errors.
+    pub warnings_as_errors: bool,
+    /// Skip the diagnostics whose key has already been seen, e.g. because cargo reports them once
+    /// per crate.
+    pub dedup: bool,
+    /// Stop after the given number of failures, as soon as another diagnostic to report follows.
+    pub max_errors: Option<i32>,
+    pub output: OutputKind,
+    /// Additional output formats, each written to its own file.
+    pub extra_outputs: Vec<(OutputKind, PathBuf)>,
+    /// Keep the ANSI colors of the rendered messages.
+    pub color: bool,
+    /// Do not print informational messages, only the diagnostics.
+    pub quiet: bool,
+    /// Skip the lines that cannot be parsed as diagnostics, instead of failing.
+    pub skip_non_json_lines: bool,
+    /// Pass the messages of cargo that are not diagnostics, e.g. `compiler-artifact` or
+    /// `build-finished`, unchanged to the reporter, instead of skipping them.
+    pub passthrough: bool,
+    /// The stream is the output of `cargo test`: skip the plain-text output of the test harness,
+    /// and report the failed tests of its JSON output.
+    pub test_output: bool,
+    /// The git pathspecs that limit the diff. A warning is logged for the diagnostics of the
+    /// files that do not match any of them.
+    pub pathspecs: Vec<String>,
+    /// The pre-existing diagnostics, which are not reported.
+    pub baseline: Option<Baseline>,
+    /// The file where to save all the diagnostics, to be used as baseline.
+    pub save_baseline: Option<PathBuf>,
+    /// The diagnostics reported by the last run, which are not reported again unless
+    /// `report_existing` is set. The cache is updated with the reported diagnostics at the end of
+    /// the stream, unless it is cut short by `max_errors`, since the diagnostics that follow would
+    /// be missing from it.
+    pub cache: Option<Cache>,
+    pub report_existing: bool,
+    /// Report the diagnostics in the order of the key, once the whole stream is read, instead of
+    /// the order of the compiler.
+    pub sort_by: Option<SortKey>,
+    /// Report the diagnostics of each file consecutively, once the whole stream is read. A header
+    /// with the file name and the number of diagnostics is printed to the standard error before
+    /// each group.
+    pub group_by_file: bool,
+    /// Report only the first diagnostics, once the whole stream is read and sorted. Unlike
+    /// `max_errors`, the compiler runs to completion.
+    pub limit: Option<usize>,
+}
+
+impl ProcessOptions {
+    /// Print an informational message, unless `quiet` is set.
+    pub(crate) fn info(&self, message: &str) {
+        if !self.quiet {
+            println!("{}", message);
+        }
     }
 
-    let diff = String::from_utf8_lossy(&output.stdout);
-    let file_changes = parse_diff(&diff)?;
-    if file_changes.is_empty() {
-        println!("No changes discovered.");
-        return Ok(());
+    /// Return `true` iff nothing is reported before the end of the stream: when the diagnostics
+    /// are sorted, grouped or limited, or written in a document without stopping at the maximum
+    /// number of failures.
+    pub(crate) fn reads_whole_stream(&self) -> bool {
+        self.sort_by.is_some()
+            || self.group_by_file
+            || self.limit.is_some()
+            || (self.output.is_document() && self.max_errors.is_none())
     }
 
-    // Filter and report JSON diagnostic messages from standard input
-    let reported = if let Some((subcommand_name, subcommand_args)) = subcommand {
-        let output_kind = value_t!(matches, "output", OutputKind).unwrap_or(OutputKind::Rendered);
+    pub(crate) fn reached_max_errors(&self, reported: i32) -> bool {
+        self.max_errors
+            .is_some_and(|max_errors| reported >= max_errors)
+    }
+}
 
-        let json_arg = if matches!(output_kind, OutputKind::GitHub) {
-            // Colorless
-            "--message-format=json"
-        } else {
-            // Colored
-            "--message-format=json-diagnostic-rendered-ansi"
-        };
+/// The outcome of [`process_stream`].
+#[derive(Clone, Copy, Default, PartialEq, Eq, Debug)]
+pub struct ProcessResult {
+    /// The number of failures, i.e. the diagnostics that should make the run fail.
+    pub reported: i32,
+    /// The number of reported warnings.
+    pub warnings: i32,
+    /// The number of reported errors, including internal compiler errors.
+    pub errors: i32,
+    /// Whether the stream was cut short by `max_errors`, i.e. whether another diagnostic to report
+    /// followed the last failure. Reaching `max_errors` at the end of the stream does not count.
+    pub stopped: bool,
+}
 
-        // Spawn the subprocess
-        let mut child = Command::new(subcommand_name)
-            .args(subcommand_args)
-            .arg(json_arg)
-            .args(&subcommand_extra_args)
-            .stdout(Stdio::piped()) // filter stdout
-            .stderr(Stdio::inherit()) // do not filter stderr
-            .spawn()
-            .with_context(|| {
-                format!(
-                    "Failed to start subprocess {:?} with arguments {:?}",
-                    subcommand, subcommand_args,
-                )
-            })?;
-
-        // Process output
-        let stdout = child
-            .stdout
-            .as_mut()
-            .with_context(|| "Failed to open standard output of subprocess")?;
-        let reported = process_stream(BufReader::new(stdout), &file_changes, output_kind)?;
-
-        // Wait for end of subprocess
-        let exit_status = child
-            .wait()
-            .with_context(|| "Failed to wait for subprocess")?;
-        // Note that cargo will return non-zero exit code even if the observed diff didn't have any
-        // errors, thus we're handling this case separately (checking for # of returned errors).
-        if !exit_status.success() && exit_status.code().unwrap_or_default() != CARGO_FAILED_EXIT_CODE {
-            bail!(
-                "Subprocess terminated with exit code {}",
-                exit_status.code().unwrap_or(-1)
-            )
-        }
-        reported
+/// Read the JSON diagnostics from `stream` and write to `out` the ones that should be reported, in
+/// the output format of `options`.
+pub fn process_stream<R: BufRead, W: Write + Send>(
+    stream: R,
+    out: &mut W,
+    file_changes: &FileChanges,
+    options: &ProcessOptions,
+) -> Result<ProcessResult> {
+    let result = {
+        let mut reporters = vec![options.output.create_reporter(&mut *out)];
+        for (output, path) in &options.extra_outputs {
+            let file = File::create(path)
+                .with_context(|| format!("Failed to create output file {:?}", path))?;
+            reporters.push(output.create_reporter(BufWriter::new(file)));
+        }
+        process_stream_with_reporter(
+            stream,
+            &mut MultiReporter::new(reporters),
+            file_changes,
+            options,
+        )?
+    };
+    if options.output == OutputKind::Json && !options.quiet {
+        // Like cargo's final `build-finished` message, to spare consumers from counting lines
+        let summary = serde_json::json!({
+            "type": "summary",
+            "total": result.reported,
+            "warnings": result.warnings,
+            "errors": result.errors,
+            "changed_files": file_changes.len(),
+        });
+        writeln!(out, "{}", summary).with_context(|| "Failed to write the summary")?;
+    }
+    Ok(result)
+}
+
+/// Read the JSON diagnostics from `stream` and pass to `reporter` the ones that should be
+/// reported. The output format of `options` is ignored.
+pub fn process_stream_with_reporter<R: BufRead>(
+    stream: R,
+    reporter: &mut dyn Reporter,
+    file_changes: &FileChanges,
+    options: &ProcessOptions,
+) -> Result<ProcessResult> {
+    let mut result = ProcessResult::default();
+    let mut seen_keys = HashSet::new();
+    let mut files_outside_pathspecs = HashSet::new();
+    let mut baseline = options.baseline.clone();
+    let mut cache = options.cache.clone();
+    let mut saved_baseline = options
+        .save_baseline
+        .as_ref()
+        .map(|path| {
+            File::create(path)
+                .map(BufWriter::new)
+                .with_context(|| format!("Failed to create baseline file {:?}", path))
+        })
+        .transpose()?;
+    // The diagnostics to report once the whole stream is read, to sort or group them
+    let buffer = options.sort_by.is_some() || options.group_by_file || options.limit.is_some();
+    let mut buffered = vec![];
+    // Once `max_errors` is reached, the stream is only read to find out whether it is cut short
+    let mut reached_max_errors = false;
+    let mut consume =
+        |json_line: String, diagnostic: Diagnostic, should_report: bool| -> Result<bool> {
+            if let Some(ref mut saved_baseline) = saved_baseline {
+                if diagnostic.message.is_some() {
+                    writeln!(saved_baseline, "{}", json_line)
+                        .with_context(|| "Failed to write the baseline")?;
+                }
+            }
+            if let Some(span) = diagnostic.primary_span() {
+                if !options.pathspecs.is_empty()
+                    && !options
+                        .pathspecs
+                        .iter()
+                        .any(|pathspec| matches_pathspec(pathspec, &span.file_name))
+                    && files_outside_pathspecs.insert(span.file_name.clone())
+                {
+                    warn!(
+                        "Diagnostics are reported in {}, which is outside of the pathspecs",
+                        span.file_name
+                    );
+                }
+            }
+            if !should_report {
+                return Ok(false);
+            }
+            if options.dedup {
+                if let Some(key) = diagnostic.diagnostic_key() {
+                    if !seen_keys.insert(key) {
+                        return Ok(false);
+                    }
+                }
+            }
+            if baseline
+                .as_mut()
+                .is_some_and(|baseline| baseline.suppress(&diagnostic))
+            {
+                debug!("Diagnostic suppressed by the baseline");
+                return Ok(false);
+            }
+            if let Some(ref mut cache) = cache {
+                if cache.contains(&diagnostic) {
+                    // Still known to the next run, even if it is not reported again
+                    cache.record(&diagnostic);
+                    if !options.report_existing {
+                        debug!("Diagnostic already reported by the last run");
+                        return Ok(false);
+                    }
+                }
+            }
+            if reached_max_errors && !diagnostic.is_passthrough() {
+                result.stopped = true;
+                return Ok(true);
+            }
+            if buffer {
+                buffered.push((json_line, diagnostic));
+                return Ok(false);
+            }
+            // Only the reported diagnostics are recorded, not the ones dropped by `limit`
+            if let Some(ref mut cache) = cache {
+                cache.record(&diagnostic);
+            }
+            if report(reporter, json_line, diagnostic, options, &mut result)? {
+                reached_max_errors = true;
+            }
+            Ok(false)
+        };
+    if options.reads_whole_stream() {
+        // Nothing is reported before the end of the stream, so the lines can be parsed and
+        // filtered in parallel. Only the state (e.g. of the deduplication) is updated in order.
+        let lines = stream
+            .lines()
+            .collect::<io::Result<Vec<_>>>()
+            .with_context(|| "Failed to read line from standard output of subprocess")?;
+        let parsed = lines
+            .into_par_iter()
+            .map(|json_line| parse_line(json_line, file_changes, options))
+            .collect::<Result<Vec<_>>>()?;
+        for (json_line, diagnostic, should_report) in parsed.into_iter().flatten() {
+            if consume(json_line, diagnostic, should_report)? {
+                break;
+            }
+        }
+    } else {
+        for maybe_line in stream.lines() {
+            let json_line = maybe_line
+                .with_context(|| "Failed to read line from standard output of subprocess")?;
+            if let Some((json_line, diagnostic, should_report)) =
+                parse_line(json_line, file_changes, options)?
+            {
+                if consume(json_line, diagnostic, should_report)? {
+                    break;
+                }
+            }
+        }
+    }
+    if let Some(sort_by) = options.sort_by {
+        sort_by.sort(&mut buffered);
+    }
+    if let Some(limit) = options.limit {
+        // The lines without a message, such as cargo's `build-finished`, are kept
+        let mut kept = 0;
+        buffered.retain(|(_, diagnostic)| {
+            diagnostic.message.is_none() || {
+                kept += 1;
+                kept <= limit
+            }
+        });
+        if kept > limit && !options.quiet {
+            eprintln!(
+                "Reported the first {} of {} diagnostic(s); run without --limit to see all.",
+                limit, kept
+            );
+        }
+    }
+    let groups = if options.group_by_file {
+        group_by_file(buffered)
     } else {
-        // Process standard input
-        let output_kind = value_t!(matches, "output", OutputKind).unwrap_or(OutputKind::Json);
-        process_stream(io::stdin().lock(), &file_changes, output_kind)?
+        vec![(None, buffered)]
     };
+    'groups: for (file_name, diagnostics) in groups {
+        if reached_max_errors && diagnostics.iter().any(|(_, d)| !d.is_passthrough()) {
+            result.stopped = true;
+            break;
+        }
+        if let Some(file_name) = file_name {
+            if !options.quiet {
+                eprintln!("{}: {} diagnostic(s)", file_name, diagnostics.len());
+            }
+        }
+        for (json_line, diagnostic) in diagnostics {
+            if reached_max_errors && !diagnostic.is_passthrough() {
+                result.stopped = true;
+                break 'groups;
+            }
+            if let Some(ref mut cache) = cache {
+                cache.record(&diagnostic);
+            }
+            if report(reporter, json_line, diagnostic, options, &mut result)? {
+                reached_max_errors = true;
+            }
+        }
+    }
+    if let Some(cache) = cache {
+        if result.stopped {
+            debug!("Not saving the cache, since the stream was cut short");
+        } else {
+            cache.save()?;
+        }
+    }
+    if let Some(mut saved_baseline) = saved_baseline {
+        saved_baseline
+            .flush()
+            .with_context(|| "Failed to write the baseline")?;
+    }
+    reporter
+        .flush()
+        .with_context(|| "Failed to write the report")?;
+    debug!(
+        "Reported {} failure(s): {} warning(s) and {} error(s)",
+        result.reported, result.warnings, result.errors
+    );
+    Ok(result)
+}
 
-    if reported > 0 {
-        bail!("Observed git diff resulted in {} error(s).", reported);
+/// Parse a line of the stream and decide whether its diagnostic is related to the changes, which
+/// only depends on the line. Return `None` for the lines to skip.
+fn parse_line(
+    json_line: String,
+    file_changes: &FileChanges,
+    options: &ProcessOptions,
+) -> Result<Option<(String, Diagnostic, bool)>> {
+    if let Some(diagnostic) = Diagnostic::from_non_diagnostic_line(&json_line) {
+        if !options.passthrough {
+            debug!("Skipping message of cargo: {:?}", json_line);
+            return Ok(None);
+        }
+        return Ok(Some((json_line, diagnostic, true)));
     }
+    let diagnostic: Diagnostic = match serde_json::from_str(&json_line) {
+        Ok(diagnostic) if options.test_output => {
+            Diagnostic::from_test_event(&json_line).unwrap_or(diagnostic)
+        }
+        Ok(diagnostic) => diagnostic,
+        Err(_) if options.test_output && !json_line.starts_with('{') => {
+            debug!("Skipping output of the test harness: {:?}", json_line);
+            return Ok(None);
+        }
+        Err(err) if options.skip_non_json_lines => {
+            warn!(
+                "Skipping line that is not a JSON diagnostic ({}): {:?}",
+                err, json_line
+            );
+            return Ok(None);
+        }
+        Err(err) => {
+            return Err(AppError::ParseError(err.to_string())).with_context(|| {
+                format!("Failed to parse JSON from standard input: {:?}", json_line)
+            })
+        }
+    };
+    // The JSON escape of the ESC character that starts the ANSI escape sequences
+    let (json_line, diagnostic) = if !options.color && json_line.contains("\\u001b") {
+        strip_colors(&json_line, &diagnostic)
+    } else {
+        (json_line, diagnostic)
+    };
+    let should_report = options.filter.apply(&diagnostic, file_changes);
+    if let Some(span) = diagnostic.primary_span() {
+        debug!(
+            "{} at {}:{}: {}",
+            diagnostic.lint_code().unwrap_or("Diagnostic without code"),
+            span.file_name,
+            span.line_start,
+            if should_report {
+                "related to the changes"
+            } else {
+                "filtered out"
+            }
+        );
+    }
+    Ok(Some((json_line, diagnostic, should_report)))
+}
 
-    println!("Success: Didn't find errors for the observed diff.");
-    Ok(())
+/// Count and report a diagnostic. Return `true` iff the maximum number of failures is reached.
+fn report(
+    reporter: &mut dyn Reporter,
+    json_line: String,
+    diagnostic: Diagnostic,
+    options: &ProcessOptions,
+    result: &mut ProcessResult,
+) -> Result<bool> {
+    if diagnostic.is_passthrough() {
+        reporter
+            .passthrough(&json_line)
+            .with_context(|| "Failed to pass through message of cargo")?;
+        return Ok(false);
+    }
+    let level = diagnostic.message.as_ref().map(|message| message.level);
+    let is_warning = matches!(level, Some(Level::Warning));
+    if is_warning {
+        result.warnings += 1;
+    } else if level.is_some_and(|level| level.is_at_least(Level::Error)) {
+        result.errors += 1;
+    }
+    let (json_line, diagnostic) = if options.warnings_as_errors && is_warning {
+        override_level(&json_line, &diagnostic, Level::Error)
+    } else {
+        (json_line, diagnostic)
+    };
+    if reporter
+        .report(&json_line, &diagnostic)
+        .with_context(|| "Failed to report diagnostic")?
+        || ((options.fail_on_warnings || options.warnings_as_errors) && is_warning)
+    {
+        // there was something to report after all, or a warning that must fail the run
+        result.reported += 1;
+        return Ok(options.reached_max_errors(result.reported));
+    }
+    Ok(false)
 }
 
-fn process_stream<T: BufRead>(
-    stream: T,
-    file_changes: &FileChanges,
-    output: OutputKind,
-) -> Result<i32> {
-    let mut reported = 0;
-    for maybe_line in stream.lines() {
-        let json_line =
-            maybe_line.with_context(|| "Failed to read line from standard output of subprocess")?;
-        let diagnostic: Diagnostic = serde_json::from_str(&json_line).with_context(|| {
-            format!("Failed to parse JSON from standard input: {:?}", json_line)
-        })?;
-        if should_report_diagnostic(&diagnostic, &file_changes) {
-            if report_diagnostic(&json_line, &diagnostic, output) {
-                // there was something to report after all
-                reported += 1;
-            }
-        }
-    }
-    Ok(reported)
+/// Return `true` iff the path is matched by a git pathspec, i.e. if the pathspec is a parent
+/// directory of the path or a glob pattern that matches it.
+fn matches_pathspec(pathspec: &str, path: &str) -> bool {
+    let pathspec = pathspec.trim_start_matches("./").trim_end_matches('/');
+    let path = path.trim_start_matches("./");
+    pathspec.is_empty()
+        || pathspec == "."
+        || path == pathspec
+        || path
+            .strip_prefix(pathspec)
+            .is_some_and(|rest| rest.starts_with('/'))
+        || Pattern::new(pathspec).is_ok_and(|pattern| pattern.matches(path))
 }
 
-/// Return `false` iff the message is a warning not related to changed lines.
-fn should_report_diagnostic(diagnostic: &Diagnostic, file_changes: &FileChanges) -> bool {
-    if let Some(ref message) = diagnostic.message {
-        if matches!(message.level, Level::Warning) || matches!(message.level, Level::Error) {
-            let mut intersects_changes = false;
-            for span in &message.spans {
-                if let Some(file_changes) = file_changes.get(&span.file_name).as_ref() {
-                    if intersect_intervals(span.line_start, span.line_end, file_changes) {
-                        intersects_changes = true;
-                        break;
-                    }
-                }
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::filter::canonicalize_path;
+
+    fn warning_json(file_name: &str, line: usize) -> serde_json::Value {
+        serde_json::json!({
+            "reason": "compiler-message",
+            "message": {
+                "rendered": "warning: unused variable: `x`",
+                "message": "unused variable: `x`",
+                "code": { "code": "unused_variables", "explanation": null },
+                "level": "warning",
+                "spans": [{
+                    "file_name": file_name,
+                    "line_start": line,
+                    "line_end": line,
+                    "column_start": 9,
+                    "column_end": 10,
+                    "is_primary": true
+                }]
+            }
+        })
+    }
+
+    fn warning_in(file_name: &str, line: usize) -> Diagnostic {
+        serde_json::from_value(warning_json(file_name, line)).unwrap()
+    }
+
+    #[test]
+    fn test_filter_exclude_files() {
+        let mut file_changes = FileChanges::new();
+        file_changes.insert("src/lib.rs".to_string(), vec![(10, 5)]);
+        file_changes.insert("tests/smoke.rs".to_string(), vec![(1, 20)]);
+        let filter = DiagnosticFilter::new()
+            .with_file_include_glob("**/*.rs")
+            .unwrap()
+            .with_file_exclude_glob("tests/**")
+            .unwrap();
+        assert!(filter.apply(&warning_in("src/lib.rs", 12), &file_changes));
+        assert!(!filter.apply(&warning_in("src/lib.rs", 20), &file_changes));
+        assert!(!filter.apply(&warning_in("tests/smoke.rs", 3), &file_changes));
+    }
+
+    #[test]
+    fn test_filter_exclude_files_normalized() {
+        let mut file_changes = FileChanges::new();
+        file_changes.insert("src/lib.rs".to_string(), vec![(10, 5)]);
+        file_changes.insert("tests/smoke.rs".to_string(), vec![(1, 20)]);
+        let filter = DiagnosticFilter::new()
+            .with_file_include_glob("src/**")
+            .unwrap()
+            .with_file_exclude_glob("tests/**")
+            .unwrap();
+        assert!(filter.apply(&warning_in("./src/lib.rs", 12), &file_changes));
+        assert!(!filter.apply(&warning_in("./tests/smoke.rs", 3), &file_changes));
+    }
+
+    #[test]
+    fn test_filter_exclude_files_path_base() {
+        let dir =
+            std::env::temp_dir().join(format!("cargo-diff-tools-globs-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("src")).unwrap();
+        std::fs::create_dir_all(dir.join("tests")).unwrap();
+        std::fs::write(dir.join("src/lib.rs"), "").unwrap();
+        std::fs::write(dir.join("tests/smoke.rs"), "").unwrap();
+
+        let file_changes = parse_diff(
+            "+++ b/src/lib.rs\n@@ -1,0 +2,2 @@\n+++ b/tests/smoke.rs\n@@ -1,0 +2,2 @@\n",
+        )
+        .unwrap()
+        .map_paths(|path| canonicalize_path(&dir, path));
+        let filter = DiagnosticFilter::new()
+            .with_path_base(&dir)
+            .with_file_exclude_glob("tests/**")
+            .unwrap();
+        assert!(filter.apply(&warning_in("src/lib.rs", 2), &file_changes));
+        assert!(!filter.apply(&warning_in("tests/smoke.rs", 2), &file_changes));
+        let file_name = dir.join("tests/smoke.rs");
+        assert!(!filter.apply(&warning_in(&file_name.to_string_lossy(), 2), &file_changes));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_filter_windows_paths() {
+        let diff = "diff --git a/src\\lib.rs b/src\\lib.rs\n--- a/src\\lib.rs\n+++ b/src\\lib.rs\n@@ -10,0 +11,2 @@\n";
+        let file_changes = parse_diff(diff).unwrap();
+        let filter = DiagnosticFilter::new();
+        assert!(filter.apply(&warning_in("src/lib.rs", 12), &file_changes));
+        assert!(filter.apply(&warning_in("src\\lib.rs", 12), &file_changes));
+        assert!(filter.apply(&warning_in(".\\src\\lib.rs", 11), &file_changes));
+        assert!(!filter.apply(&warning_in("src\\lib.rs", 20), &file_changes));
+    }
+
+    #[test]
+    fn test_filter_target() {
+        let file_changes =
+            parse_diff("+++ b/target/debug/build/out.rs\n@@ -1,0 +2,2 @@\n").unwrap();
+        let filter = DiagnosticFilter::new().with_target("x86_64-unknown-linux-gnu");
+        let file_name = "target/x86_64-unknown-linux-gnu/debug/build/out.rs";
+        assert!(filter.apply(&warning_in(file_name, 2), &file_changes));
+        assert!(!DiagnosticFilter::new().apply(&warning_in(file_name, 2), &file_changes));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_filter_symlinks() {
+        let dir =
+            std::env::temp_dir().join(format!("cargo-diff-tools-symlinks-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("external")).unwrap();
+        std::fs::create_dir_all(dir.join("src")).unwrap();
+        std::fs::write(dir.join("external/foo.rs"), "").unwrap();
+        std::os::unix::fs::symlink("../external/foo.rs", dir.join("src/foo.rs")).unwrap();
+
+        // The diff records the symlink, the diagnostic records the real path
+        let file_changes = parse_diff("+++ b/src/foo.rs\n@@ -1,0 +2,2 @@\n")
+            .unwrap()
+            .map_paths(|path| canonicalize_path(&dir, path));
+        let filter = DiagnosticFilter::new().with_path_base(&dir);
+        assert!(filter.apply(&warning_in("external/foo.rs", 2), &file_changes));
+        assert!(filter.apply(&warning_in("src/foo.rs", 3), &file_changes));
+        assert!(!filter.apply(&warning_in("external/foo.rs", 4), &file_changes));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_filter_generated_html() {
+        let mut file_changes = FileChanges::new();
+        file_changes.insert("src/lib.rs".to_string(), vec![(10, 5)]);
+        file_changes.insert("target/doc/crate/index.html".to_string(), vec![(1, 20)]);
+        let filter = DiagnosticFilter::new();
+        assert!(filter.apply(&warning_in("src/lib.rs", 12), &file_changes));
+        assert!(!filter.apply(&warning_in("target/doc/crate/index.html", 3), &file_changes));
+    }
+
+    #[test]
+    fn test_filter_follow_children() {
+        let mut file_changes = FileChanges::new();
+        file_changes.insert("src/lib.rs".to_string(), vec![(10, 5)]);
+        let mut json = warning_json("src/lib.rs", 30);
+        json["message"]["children"] = serde_json::json!([{
+            "rendered": null,
+            "message": "the variable was declared here",
+            "code": null,
+            "level": "note",
+            "spans": [{
+                "file_name": "src/lib.rs",
+                "line_start": 12,
+                "line_end": 12,
+                "column_start": 1,
+                "column_end": 5,
+                "is_primary": true
+            }],
+            "children": []
+        }]);
+        let diagnostic: Diagnostic = serde_json::from_value(json).unwrap();
+        assert!(!DiagnosticFilter::new().apply(&diagnostic, &file_changes));
+        assert!(DiagnosticFilter::new()
+            .with_follow_children(true)
+            .apply(&diagnostic, &file_changes));
+    }
+
+    #[test]
+    fn test_process_stream() {
+        let mut file_changes = FileChanges::new();
+        file_changes.insert("src/lib.rs".to_string(), vec![(10, 5)]);
+        let stream = format!(
+            "{}\n{}\n",
+            warning_json("src/lib.rs", 12),
+            warning_json("src/lib.rs", 20)
+        );
+        let options = ProcessOptions {
+            filter: DiagnosticFilter::new(),
+            fail_on_warnings: false,
+            warnings_as_errors: false,
+            dedup: true,
+            max_errors: None,
+            output: OutputKind::Rendered,
+            extra_outputs: vec![],
+            color: false,
+            quiet: false,
+            skip_non_json_lines: false,
+            passthrough: true,
+            test_output: false,
+            baseline: None,
+            save_baseline: None,
+            cache: None,
+            report_existing: false,
+            sort_by: None,
+            group_by_file: false,
+            limit: None,
+            pathspecs: vec![],
+        };
+        let mut out = Vec::new();
+        let result = process_stream(stream.as_bytes(), &mut out, &file_changes, &options).unwrap();
+        assert_eq!(
+            result,
+            ProcessResult {
+                reported: 1,
+                warnings: 1,
+                errors: 0,
+                stopped: false,
+            }
+        );
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "warning: unused variable: `x`\n"
+        );
+
+        // A reporter of another crate
+        #[derive(Default)]
+        struct LineReporter {
+            lines: Vec<usize>,
+            flushed: bool,
+        }
+        impl Reporter for LineReporter {
+            fn report(&mut self, _line: &str, diagnostic: &Diagnostic) -> Result<bool> {
+                self.lines
+                    .extend(diagnostic.primary_span().map(|span| span.line_start));
+                Ok(true)
             }
-            if !intersects_changes {
-                return false;
+            fn flush(&mut self) -> Result<()> {
+                self.flushed = true;
+                Ok(())
             }
         }
+        let mut reporter = LineReporter::default();
+        let result =
+            process_stream_with_reporter(stream.as_bytes(), &mut reporter, &file_changes, &options)
+                .unwrap();
+        assert_eq!(result.reported, 1);
+        assert_eq!(reporter.lines, vec![12]);
+        assert!(reporter.flushed);
+
+        // The diagnostics are sorted, then limited, once the whole stream is read
+        file_changes.insert("src/lib.rs".to_string(), vec![(10, 15)]);
+        let stream = format!(
+            "{}\n{}\n{}\n",
+            warning_json("src/lib.rs", 20),
+            warning_json("src/lib.rs", 24),
+            warning_json("src/lib.rs", 12)
+        );
+        let options = ProcessOptions {
+            sort_by: Some(SortKey::Line),
+            limit: Some(2),
+            quiet: true,
+            ..options
+        };
+        let mut reporter = LineReporter::default();
+        let result =
+            process_stream_with_reporter(stream.as_bytes(), &mut reporter, &file_changes, &options)
+                .unwrap();
+        assert_eq!(result.reported, 2);
+        assert_eq!(reporter.lines, vec![12, 20]);
+
+        // The diagnostics dropped by the limit are not cached, so the next run reports them
+        let dir =
+            std::env::temp_dir().join(format!("cargo-diff-tools-limit-{}", std::process::id()));
+        for expected_lines in [vec![12, 20], vec![24]] {
+            let options = ProcessOptions {
+                cache: Some(Cache::load(&dir).unwrap()),
+                ..options.clone()
+            };
+            let mut reporter = LineReporter::default();
+            process_stream_with_reporter(stream.as_bytes(), &mut reporter, &file_changes, &options)
+                .unwrap();
+            assert_eq!(reporter.lines, expected_lines);
+        }
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        // The other messages of cargo are written unchanged to the JSON output, but not counted
+        let artifact = r#"{"reason":"compiler-artifact","package_id":"foo 0.1.0","fresh":true}"#;
+        let finished = r#"{"reason":"build-finished","success":true}"#;
+        let warning = warning_json("src/lib.rs", 12).to_string();
+        let stream = format!("{}\n{}\n{}\n", artifact, warning, finished);
+        let options = ProcessOptions {
+            output: OutputKind::Json,
+            sort_by: None,
+            limit: None,
+            ..options
+        };
+        let mut out = Vec::new();
+        let result = process_stream(stream.as_bytes(), &mut out, &file_changes, &options).unwrap();
+        assert_eq!(result.reported, 1);
+        assert_eq!(String::from_utf8(out).unwrap(), stream);
+        let options = ProcessOptions {
+            passthrough: false,
+            ..options
+        };
+        let mut out = Vec::new();
+        let result = process_stream(stream.as_bytes(), &mut out, &file_changes, &options).unwrap();
+        assert_eq!(result.reported, 1);
+        assert_eq!(String::from_utf8(out).unwrap(), format!("{}\n", warning));
+
+        // Reaching the maximum number of failures only stops the stream if another one follows
+        let options = ProcessOptions {
+            max_errors: Some(1),
+            passthrough: true,
+            ..options
+        };
+        let mut out = Vec::new();
+        let result = process_stream(stream.as_bytes(), &mut out, &file_changes, &options).unwrap();
+        assert_eq!(result.reported, 1);
+        assert!(!result.stopped);
+        assert_eq!(String::from_utf8(out).unwrap(), stream);
+        let stream = format!("{}\n{}\n", warning, warning_json("src/lib.rs", 20));
+        let result =
+            process_stream(stream.as_bytes(), &mut Vec::new(), &file_changes, &options).unwrap();
+        assert_eq!(result.reported, 1);
+        assert!(result.stopped);
+        let options = ProcessOptions {
+            sort_by: Some(SortKey::Line),
+            ..options
+        };
+        let result =
+            process_stream(stream.as_bytes(), &mut Vec::new(), &file_changes, &options).unwrap();
+        assert_eq!(result.reported, 1);
+        assert!(result.stopped);
+
+        // The cache is not saved when the stream is cut short
+        let dir =
+            std::env::temp_dir().join(format!("cargo-diff-tools-stopped-{}", std::process::id()));
+        let options = ProcessOptions {
+            cache: Some(Cache::load(&dir).unwrap()),
+            ..options
+        };
+        let result =
+            process_stream(stream.as_bytes(), &mut Vec::new(), &file_changes, &options).unwrap();
+        assert!(result.stopped);
+        assert!(!dir.exists());
+        let stream = format!("{}\n", warning);
+        let result =
+            process_stream(stream.as_bytes(), &mut Vec::new(), &file_changes, &options).unwrap();
+        assert!(!result.stopped);
+        assert!(dir.exists());
+        std::fs::remove_dir_all(&dir).unwrap();
     }
-    true
 }
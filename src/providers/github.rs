@@ -0,0 +1,183 @@
+use super::DiffProvider;
+use anyhow::{anyhow, bail, Context, Result};
+use std::env;
+use std::io::Read;
+use std::time::Duration;
+
+/// The GitHub REST API used when `GITHUB_API_URL` is not set, as it is in GitHub Actions.
+const DEFAULT_API_URL: &str = "https://api.github.com";
+
+/// Fetch the diff of a GitHub pull request from the REST API, without a local clone.
+#[derive(Clone, PartialEq, Eq)]
+pub struct GitHubApiDiffProvider {
+    pub owner: String,
+    pub repo: String,
+    pub number: u64,
+    /// The token used to authenticate, which is needed for private repositories and to avoid
+    /// the low rate limit of anonymous requests.
+    pub token: Option<String>,
+    pub api_url: String,
+}
+
+impl GitHubApiDiffProvider {
+    /// Parse a pull request given as `OWNER/REPO#NUMBER`. The API URL and the token are read from
+    /// the `GITHUB_API_URL` and `GITHUB_TOKEN` environment variables.
+    pub fn from_spec(spec: &str) -> Result<Self> {
+        let (owner, repo, number) = parse_pull_request(spec)?;
+        Ok(GitHubApiDiffProvider {
+            owner: owner.to_string(),
+            repo: repo.to_string(),
+            number,
+            token: env::var("GITHUB_TOKEN")
+                .ok()
+                .filter(|token| !token.is_empty()),
+            api_url: env::var("GITHUB_API_URL").unwrap_or_else(|_| DEFAULT_API_URL.to_string()),
+        })
+    }
+
+    fn url(&self) -> String {
+        format!(
+            "{}/repos/{}/{}/pulls/{}",
+            self.api_url.trim_end_matches('/'),
+            self.owner,
+            self.repo,
+            self.number
+        )
+    }
+}
+
+/// Hide the token from the debug output.
+impl std::fmt::Debug for GitHubApiDiffProvider {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("GitHubApiDiffProvider")
+            .field("owner", &self.owner)
+            .field("repo", &self.repo)
+            .field("number", &self.number)
+            .field("token", &self.token.as_ref().map(|_| "***"))
+            .field("api_url", &self.api_url)
+            .finish()
+    }
+}
+
+impl DiffProvider for GitHubApiDiffProvider {
+    fn get_diff(&self) -> Result<String> {
+        let pull_request = format!("{}/{}#{}", self.owner, self.repo, self.number);
+        let mut request = ureq::AgentBuilder::new()
+            .timeout(Duration::from_secs(60))
+            .build()
+            .get(&self.url())
+            .set("Accept", "application/vnd.github.v3.diff")
+            .set(
+                "User-Agent",
+                concat!("cargo-diff-tools/", env!("CARGO_PKG_VERSION")),
+            )
+            .set("X-GitHub-Api-Version", "2022-11-28");
+        if let Some(ref token) = self.token {
+            request = request.set("Authorization", &format!("Bearer {}", token));
+        }
+        let response = match request.call() {
+            Ok(response) => response,
+            Err(ureq::Error::Status(status, response)) => {
+                return Err(status_error(status, &response, self.token.is_some())).with_context(
+                    || format!("Failed to fetch the diff of pull request {}", pull_request),
+                )
+            }
+            Err(err) => {
+                return Err(anyhow!(err)).with_context(|| {
+                    format!("Failed to fetch the diff of pull request {}", pull_request)
+                })
+            }
+        };
+        // Do not use `into_string`, which truncates the large diffs
+        let mut diff = String::new();
+        response
+            .into_reader()
+            .read_to_string(&mut diff)
+            .with_context(|| format!("Failed to read the diff of pull request {}", pull_request))?;
+        Ok(diff)
+    }
+}
+
+/// Explain an error status of the GitHub API.
+fn status_error(status: u16, response: &ureq::Response, has_token: bool) -> anyhow::Error {
+    let rate_limited = response.header("x-ratelimit-remaining") == Some("0");
+    match status {
+        401 => anyhow!("Authentication failed (401); check that GITHUB_TOKEN is a valid token"),
+        403 | 429 if rate_limited => anyhow!(
+            "The rate limit of the GitHub API is exceeded ({}){}{}",
+            status,
+            match response.header("x-ratelimit-reset") {
+                Some(reset) => format!("; it resets at the UNIX time {}", reset),
+                None => String::new(),
+            },
+            if has_token {
+                ""
+            } else {
+                "; set GITHUB_TOKEN to raise the limit"
+            }
+        ),
+        403 => anyhow!(
+            "Access denied (403); check that GITHUB_TOKEN can read the pull requests of the repository"
+        ),
+        404 => anyhow!(
+            "Pull request not found (404){}",
+            if has_token {
+                ""
+            } else {
+                "; set GITHUB_TOKEN to access a private repository"
+            }
+        ),
+        406 => anyhow!("The diff is too large to be returned by the GitHub API (406)"),
+        _ => anyhow!("The GitHub API responded with status {}", status),
+    }
+}
+
+/// Split a pull request given as `OWNER/REPO#NUMBER`.
+fn parse_pull_request(spec: &str) -> Result<(&str, &str, u64)> {
+    let parsed = spec.split_once('#').and_then(|(repository, number)| {
+        let (owner, repo) = repository.split_once('/')?;
+        let is_name = |name: &str| {
+            !name.is_empty()
+                && name != "."
+                && name != ".."
+                && name
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
+        };
+        if !is_name(owner) || !is_name(repo) {
+            return None;
+        }
+        Some((owner, repo, number.parse().ok().filter(|&n| n > 0)?))
+    });
+    match parsed {
+        Some(parsed) => Ok(parsed),
+        None => bail!(
+            "{:?} is not a pull request of the form OWNER/REPO#NUMBER, e.g. rust-lang/rust#1",
+            spec
+        ),
+    }
+}
+
+/// Reject the pull requests that are not of the form `OWNER/REPO#NUMBER`.
+pub(crate) fn is_pull_request(value: String) -> std::result::Result<(), String> {
+    parse_pull_request(&value)
+        .map(|_| ())
+        .map_err(|err| err.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_pull_request() {
+        assert_eq!(
+            parse_pull_request("warpdotdev/cargo-diff-tools#42").unwrap(),
+            ("warpdotdev", "cargo-diff-tools", 42)
+        );
+        assert!(parse_pull_request("warpdotdev/cargo-diff-tools").is_err());
+        assert!(parse_pull_request("cargo-diff-tools#42").is_err());
+        assert!(parse_pull_request("warpdotdev/cargo-diff-tools#0").is_err());
+        assert!(parse_pull_request("warpdotdev/../x#1").is_err());
+    }
+}
@@ -0,0 +1,323 @@
+use super::DiffProvider;
+use anyhow::{anyhow, bail, Context, Result};
+use serde::Deserialize;
+use std::env;
+use std::time::Duration;
+
+/// The GitLab REST API used when `CI_API_V4_URL` is not set, as it is in GitLab CI/CD.
+const DEFAULT_API_URL: &str = "https://gitlab.com/api/v4";
+
+/// The number of files requested per page, which is the maximum allowed by GitLab.
+const PER_PAGE: usize = 100;
+
+/// Fetch the diff of a GitLab merge request from the REST API, without a local clone.
+#[derive(Clone, PartialEq, Eq)]
+pub struct GitLabApiDiffProvider {
+    /// The numeric ID of the project or its path, e.g. `group/project`.
+    pub project: String,
+    pub iid: u64,
+    /// The token used to authenticate, which is needed for private projects.
+    pub token: Option<String>,
+    pub api_url: String,
+}
+
+/// The diff of a file of a merge request, as returned by the GitLab API.
+#[derive(Clone, PartialEq, Eq, Debug, Deserialize)]
+struct MergeRequestDiff {
+    old_path: String,
+    new_path: String,
+    #[serde(default)]
+    a_mode: Option<String>,
+    #[serde(default)]
+    b_mode: Option<String>,
+    #[serde(default)]
+    new_file: bool,
+    #[serde(default)]
+    renamed_file: bool,
+    #[serde(default)]
+    deleted_file: bool,
+    /// The hunks of the diff, without the header of the file.
+    #[serde(default)]
+    diff: String,
+}
+
+impl GitLabApiDiffProvider {
+    /// Parse a merge request given as `PROJECT!IID`. The API URL and the token are read from the
+    /// `CI_API_V4_URL` and `GITLAB_TOKEN` environment variables.
+    pub fn from_spec(spec: &str) -> Result<Self> {
+        let (project, iid) = parse_merge_request(spec)?;
+        Ok(GitLabApiDiffProvider {
+            project: project.to_string(),
+            iid,
+            token: env::var("GITLAB_TOKEN")
+                .ok()
+                .filter(|token| !token.is_empty()),
+            api_url: env::var("CI_API_V4_URL").unwrap_or_else(|_| DEFAULT_API_URL.to_string()),
+        })
+    }
+
+    fn url(&self, page: &str) -> String {
+        format!(
+            "{}/projects/{}/merge_requests/{}/diffs?per_page={}&page={}",
+            self.api_url.trim_end_matches('/'),
+            // A project path is a single segment of the URL
+            self.project.replace('/', "%2F"),
+            self.iid,
+            PER_PAGE,
+            page
+        )
+    }
+}
+
+/// Hide the token from the debug output.
+impl std::fmt::Debug for GitLabApiDiffProvider {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("GitLabApiDiffProvider")
+            .field("project", &self.project)
+            .field("iid", &self.iid)
+            .field("token", &self.token.as_ref().map(|_| "***"))
+            .field("api_url", &self.api_url)
+            .finish()
+    }
+}
+
+impl DiffProvider for GitLabApiDiffProvider {
+    fn get_diff(&self) -> Result<String> {
+        let merge_request = format!("{}!{}", self.project, self.iid);
+        let agent = ureq::AgentBuilder::new()
+            .timeout(Duration::from_secs(60))
+            .build();
+        let mut diffs: Vec<MergeRequestDiff> = vec![];
+        let mut page = "1".to_string();
+        loop {
+            let mut request = agent.get(&self.url(&page)).set(
+                "User-Agent",
+                concat!("cargo-diff-tools/", env!("CARGO_PKG_VERSION")),
+            );
+            if let Some(ref token) = self.token {
+                request = request.set("PRIVATE-TOKEN", token);
+            }
+            let response = match request.call() {
+                Ok(response) => response,
+                Err(ureq::Error::Status(status, response)) => {
+                    return Err(status_error(status, &response, self.token.is_some())).with_context(
+                        || {
+                            format!(
+                                "Failed to fetch the diff of merge request {}",
+                                merge_request
+                            )
+                        },
+                    )
+                }
+                Err(err) => {
+                    return Err(anyhow!(err)).with_context(|| {
+                        format!(
+                            "Failed to fetch the diff of merge request {}",
+                            merge_request
+                        )
+                    })
+                }
+            };
+            // The header is empty on the last page
+            let next_page = response
+                .header("x-next-page")
+                .unwrap_or_default()
+                .to_string();
+            let page_diffs: Vec<MergeRequestDiff> = serde_json::from_reader(response.into_reader())
+                .with_context(|| {
+                    format!(
+                        "Failed to parse the diff of merge request {}",
+                        merge_request
+                    )
+                })?;
+            diffs.extend(page_diffs);
+            if next_page.is_empty() {
+                break;
+            }
+            page = next_page;
+        }
+        Ok(unified_diff(&diffs))
+    }
+}
+
+/// Rebuild a unified diff from the diffs of the files, adding the headers that GitLab omits. The
+/// diffs keep their 3 lines of context, which [`parse_diff`](crate::parse_diff) does not count as
+/// changed.
+fn unified_diff(diffs: &[MergeRequestDiff]) -> String {
+    let mut unified = String::new();
+    for file in diffs {
+        unified.push_str(&format!(
+            "diff --git a/{} b/{}\n",
+            file.old_path, file.new_path
+        ));
+        if file.new_file {
+            unified.push_str(&format!(
+                "new file mode {}\n",
+                file.b_mode.as_deref().unwrap_or("100644")
+            ));
+        } else if file.deleted_file {
+            unified.push_str(&format!(
+                "deleted file mode {}\n",
+                file.a_mode.as_deref().unwrap_or("100644")
+            ));
+        }
+        if file.renamed_file {
+            unified.push_str(&format!(
+                "rename from {}\nrename to {}\n",
+                file.old_path, file.new_path
+            ));
+        }
+        if file.diff.is_empty() {
+            continue;
+        }
+        if file.diff.starts_with("@@") {
+            if file.new_file {
+                unified.push_str("--- /dev/null\n");
+            } else {
+                unified.push_str(&format!("--- a/{}\n", file.old_path));
+            }
+            if file.deleted_file {
+                unified.push_str("+++ /dev/null\n");
+            } else {
+                unified.push_str(&format!("+++ b/{}\n", file.new_path));
+            }
+        }
+        unified.push_str(&file.diff);
+        if !file.diff.ends_with('\n') {
+            unified.push('\n');
+        }
+    }
+    unified
+}
+
+/// Explain an error status of the GitLab API.
+fn status_error(status: u16, response: &ureq::Response, has_token: bool) -> anyhow::Error {
+    match status {
+        401 => anyhow!("Authentication failed (401); check that GITLAB_TOKEN is a valid token"),
+        403 => anyhow!(
+            "Access denied (403); check that GITLAB_TOKEN can read the merge requests of the project"
+        ),
+        404 => anyhow!(
+            "Merge request not found (404){}",
+            if has_token {
+                ""
+            } else {
+                "; set GITLAB_TOKEN to access a private project"
+            }
+        ),
+        429 => anyhow!(
+            "The rate limit of the GitLab API is exceeded (429){}",
+            match response.header("retry-after") {
+                Some(seconds) => format!("; retry after {} seconds", seconds),
+                None => String::new(),
+            }
+        ),
+        _ => anyhow!("The GitLab API responded with status {}", status),
+    }
+}
+
+/// Split a merge request given as `PROJECT!IID`, the project being a numeric ID or a path.
+fn parse_merge_request(spec: &str) -> Result<(&str, u64)> {
+    let parsed = spec.rsplit_once('!').and_then(|(project, iid)| {
+        let is_project = !project.is_empty()
+            && project.split('/').all(|segment| {
+                !segment.is_empty()
+                    && segment != "."
+                    && segment != ".."
+                    && segment
+                        .chars()
+                        .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
+            });
+        if !is_project {
+            return None;
+        }
+        Some((project, iid.parse().ok().filter(|&n| n > 0)?))
+    });
+    match parsed {
+        Some(parsed) => Ok(parsed),
+        None => bail!(
+            "{:?} is not a merge request of the form PROJECT!IID, e.g. 278964!42 or gitlab-org/gitlab!42",
+            spec
+        ),
+    }
+}
+
+/// Reject the merge requests that are not of the form `PROJECT!IID`.
+pub(crate) fn is_merge_request(value: String) -> std::result::Result<(), String> {
+    parse_merge_request(&value)
+        .map(|_| ())
+        .map_err(|err| err.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse_diff;
+
+    #[test]
+    fn test_parse_merge_request() {
+        assert_eq!(parse_merge_request("278964!42").unwrap(), ("278964", 42));
+        assert_eq!(
+            parse_merge_request("gitlab-org/gitlab!7").unwrap(),
+            ("gitlab-org/gitlab", 7)
+        );
+        assert!(parse_merge_request("gitlab-org/gitlab").is_err());
+        assert!(parse_merge_request("gitlab-org//gitlab!7").is_err());
+        assert!(parse_merge_request("278964!0").is_err());
+    }
+
+    #[test]
+    fn test_unified_diff() {
+        let diffs: Vec<MergeRequestDiff> = serde_json::from_value(serde_json::json!([
+            {
+                "old_path": "src/lib.rs",
+                "new_path": "src/lib.rs",
+                "a_mode": "100644",
+                "b_mode": "100644",
+                "new_file": false,
+                "renamed_file": false,
+                "deleted_file": false,
+                "diff": "@@ -10,0 +11,2 @@\n+a\n+b\n"
+            },
+            {
+                "old_path": "src/old.rs",
+                "new_path": "src/new.rs",
+                "new_file": false,
+                "renamed_file": true,
+                "deleted_file": false,
+                "diff": "@@ -1 +1 @@\n-x\n+y"
+            },
+            {
+                "old_path": "src/main.rs",
+                "new_path": "src/main.rs",
+                "b_mode": "100644",
+                "new_file": true,
+                "renamed_file": false,
+                "deleted_file": false,
+                "diff": "@@ -0,0 +1,3 @@\n+fn main() {\n+}\n+\n"
+            }
+        ]))
+        .unwrap();
+        let file_changes = parse_diff(&unified_diff(&diffs)).unwrap();
+        assert_eq!(&file_changes["src/lib.rs"], &[(11, 2)]);
+        assert_eq!(&file_changes["src/new.rs"], &[(1, 1)]);
+        assert_eq!(file_changes.renamed_to("src/old.rs"), Some("src/new.rs"));
+        assert_eq!(&file_changes["src/main.rs"], &[(1, 3)]);
+    }
+
+    #[test]
+    fn test_unified_diff_context() {
+        let diffs: Vec<MergeRequestDiff> = serde_json::from_value(serde_json::json!([{
+            "old_path": "src/lib.rs",
+            "new_path": "src/lib.rs",
+            "new_file": false,
+            "renamed_file": false,
+            "deleted_file": false,
+            "diff": "@@ -8,6 +8,8 @@ fn main() {\n     let a = 1;\n     let b = 2;\n     let c = 3;\n+    let d = 4;\n+    let e = 5;\n     run(a, b, c);\n }\n \n"
+        }]))
+        .unwrap();
+        let file_changes = parse_diff(&unified_diff(&diffs)).unwrap();
+        // Only the added lines, not the 3 lines of context around them
+        assert_eq!(&file_changes["src/lib.rs"], &[(11, 2)]);
+    }
+}
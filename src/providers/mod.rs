@@ -0,0 +1,139 @@
+use crate::diff::DiffTool;
+use crate::error::AppError;
+use anyhow::{Context, Result};
+use std::ffi::OsString;
+use std::fs;
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+pub(crate) mod github;
+pub(crate) mod gitlab;
+
+pub use self::github::GitHubApiDiffProvider;
+pub use self::gitlab::GitLabApiDiffProvider;
+
+/// A source of the diff whose changes the diagnostics are filtered by.
+pub trait DiffProvider {
+    /// Return the diff, in the unified format parsed by [`parse_diff`](crate::parse_diff).
+    fn get_diff(&self) -> Result<String>;
+}
+
+/// Run `git diff` (or `hg diff`, or `svn diff`) without context lines.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct GitDiffProvider {
+    pub tool: DiffTool,
+    /// The arguments that follow `diff --unified=0`.
+    pub args: Vec<String>,
+    /// The git executable, run when `tool` is git.
+    pub git_binary: PathBuf,
+    /// The working tree and git directory that `git` operates on, if they are not the ones of the
+    /// current directory.
+    pub work_tree: Option<PathBuf>,
+    pub git_dir: Option<PathBuf>,
+}
+
+impl GitDiffProvider {
+    pub fn new(args: Vec<String>) -> Self {
+        GitDiffProvider {
+            tool: DiffTool::Git,
+            args,
+            git_binary: PathBuf::from("git"),
+            work_tree: None,
+            git_dir: None,
+        }
+    }
+}
+
+impl DiffProvider for GitDiffProvider {
+    fn get_diff(&self) -> Result<String> {
+        let (diff_program, diff_args) = self.tool.command();
+        let mut command = if self.tool == DiffTool::Git {
+            git_command(
+                &self.git_binary,
+                self.work_tree.as_deref(),
+                self.git_dir.as_deref(),
+            )
+        } else {
+            Command::new(diff_program)
+        };
+        let output = command
+            .args(diff_args)
+            .args(&self.args)
+            .output()
+            .with_context(|| {
+                format!(
+                    "Failed to start `{} diff`",
+                    Path::new(command.get_program()).display()
+                )
+            })?;
+
+        if !output.stderr.is_empty() {
+            io::stderr().write_all(&output.stderr).with_context(|| {
+                format!("Failed to report the stderr of `{} diff`", diff_program)
+            })?;
+        }
+        if !output.status.success() {
+            return Err(AppError::GitDiffFailed(output.status))
+                .with_context(|| format!("Failed to run `{} diff`", diff_program));
+        }
+
+        Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+    }
+}
+
+/// Read the diff from a file.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct FileDiffProvider {
+    pub path: PathBuf,
+}
+
+impl DiffProvider for FileDiffProvider {
+    fn get_diff(&self) -> Result<String> {
+        fs::read_to_string(&self.path)
+            .with_context(|| format!("Failed to read diff from file {:?}", self.path))
+    }
+}
+
+/// Read the whole diff from the standard input.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct StdinDiffProvider;
+
+impl DiffProvider for StdinDiffProvider {
+    fn get_diff(&self) -> Result<String> {
+        let mut diff = String::new();
+        io::stdin()
+            .read_to_string(&mut diff)
+            .with_context(|| "Failed to read diff from standard input")?;
+        Ok(diff)
+    }
+}
+
+/// Return a `git` command, run with `git_binary`, that operates on the given working tree and git directory, if any.
+pub(crate) fn git_command(
+    git_binary: &Path,
+    work_tree: Option<&Path>,
+    git_dir: Option<&Path>,
+) -> Command {
+    let mut command = Command::new(git_binary);
+    if let Some(work_tree) = work_tree {
+        command.arg("-C").arg(work_tree);
+    }
+    if let Some(git_dir) = git_dir {
+        let mut git_dir_arg = OsString::from("--git-dir=");
+        git_dir_arg.push(git_dir);
+        command.arg(git_dir_arg);
+    }
+    command
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_git_diff_provider_failure() {
+        let provider = GitDiffProvider::new(vec!["--no-such-option".to_string()]);
+        assert!(provider.get_diff().is_err());
+    }
+}
@@ -0,0 +1,1169 @@
+use crate::diagnostics::{Diagnostic, Level, Message};
+use anyhow::{Context, Result};
+use clap::{arg_enum, crate_version};
+use lazy_static::lazy_static;
+use regex::Regex;
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashSet};
+use std::env;
+use std::io::{self, IsTerminal, Write};
+use std::path::Path;
+
+mod r#trait;
+
+pub use self::r#trait::Reporter;
+
+arg_enum! {
+    #[derive(Copy, Clone, PartialEq, Eq, Debug)]
+    pub enum OutputKind {
+        Auto,
+        Json,
+        Rendered,
+        GitHub,
+        Sarif,
+        JUnit,
+        GitLab,
+        TeamCity,
+        Html,
+        Markdown,
+        Csv,
+    }
+}
+
+impl OutputKind {
+    /// Resolve `Auto` to the output kind that best suits the environment: GitHub workflow commands
+    /// in GitHub Actions, rendered messages in a terminal, and JSON otherwise. Other output kinds
+    /// are returned unchanged.
+    pub fn resolve(self) -> OutputKind {
+        match self {
+            OutputKind::Auto if env::var_os("GITHUB_ACTIONS").is_some() => OutputKind::GitHub,
+            OutputKind::Auto if io::stdout().is_terminal() => OutputKind::Rendered,
+            OutputKind::Auto => OutputKind::Json,
+            output => output,
+        }
+    }
+
+    /// Return the reporter of the output kind, which writes to `out`. `Auto` is resolved first.
+    pub fn create_reporter<'a, W: Write + Send + 'a>(self, out: W) -> Box<dyn Reporter + 'a> {
+        match self.resolve() {
+            OutputKind::Json => Box::new(JsonReporter::new(out)),
+            OutputKind::Rendered => Box::new(RenderedReporter::new(out)),
+            OutputKind::GitHub => Box::new(GitHubReporter::new(out)),
+            OutputKind::TeamCity => Box::new(TeamCityReporter::new(out)),
+            kind => Box::new(DocumentReporter::new(out, kind)),
+        }
+    }
+
+    /// Return `true` iff the output is a document, which is written once all the diagnostics are
+    /// reported.
+    pub fn is_document(self) -> bool {
+        matches!(
+            self.resolve(),
+            OutputKind::Sarif
+                | OutputKind::JUnit
+                | OutputKind::GitLab
+                | OutputKind::Html
+                | OutputKind::Markdown
+                | OutputKind::Csv
+        )
+    }
+
+    /// Return `true` iff the diagnostics can be written as they arrive, i.e. if the output is not a
+    /// document. `Auto` is resolved first.
+    pub fn is_streaming_compatible(self) -> bool {
+        !self.is_document()
+    }
+
+    /// Return `true` iff the output contains the ANSI-colored messages rendered by the compiler.
+    pub fn is_colored(self) -> bool {
+        matches!(self, OutputKind::Json | OutputKind::Rendered)
+    }
+}
+
+arg_enum! {
+    #[derive(Copy, Clone, PartialEq, Eq, Debug)]
+    pub enum ColorChoice {
+        Auto,
+        Always,
+        Never,
+    }
+}
+
+impl ColorChoice {
+    /// Return `true` iff the output should contain ANSI colors. `Auto` colors the output only if
+    /// the standard output is a terminal.
+    pub fn is_enabled(self) -> bool {
+        match self {
+            ColorChoice::Auto => io::stdout().is_terminal(),
+            ColorChoice::Always => true,
+            ColorChoice::Never => false,
+        }
+    }
+}
+
+/// Write each diagnostic as the JSON line it was parsed from.
+pub struct JsonReporter<W> {
+    out: W,
+}
+
+impl<W: Write + Send> JsonReporter<W> {
+    pub fn new(out: W) -> Self {
+        JsonReporter { out }
+    }
+}
+
+impl<W: Write + Send> Reporter for JsonReporter<W> {
+    fn report(&mut self, line: &str, _diagnostic: &Diagnostic) -> Result<bool> {
+        writeln!(self.out, "{}", line)?;
+        Ok(true)
+    }
+
+    fn passthrough(&mut self, line: &str) -> Result<()> {
+        writeln!(self.out, "{}", line)?;
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        Ok(self.out.flush()?)
+    }
+}
+
+/// Write the messages rendered by the compiler, as cargo does.
+pub struct RenderedReporter<W> {
+    out: W,
+}
+
+impl<W: Write + Send> RenderedReporter<W> {
+    pub fn new(out: W) -> Self {
+        RenderedReporter { out }
+    }
+}
+
+impl<W: Write + Send> Reporter for RenderedReporter<W> {
+    fn report(&mut self, _line: &str, diagnostic: &Diagnostic) -> Result<bool> {
+        if let Some(rendered) = diagnostic.rendered() {
+            writeln!(self.out, "{}", rendered)?;
+            return Ok(true);
+        }
+        Ok(false)
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        Ok(self.out.flush()?)
+    }
+}
+
+/// Write GitHub Actions workflow commands, which annotate the changes of a pull request.
+pub struct GitHubReporter<W> {
+    out: W,
+}
+
+impl<W: Write + Send> GitHubReporter<W> {
+    pub fn new(out: W) -> Self {
+        GitHubReporter { out }
+    }
+}
+
+impl<W: Write + Send> Reporter for GitHubReporter<W> {
+    fn report(&mut self, _line: &str, diagnostic: &Diagnostic) -> Result<bool> {
+        if let Some(annotation) = diagnostic.message.as_ref().and_then(github_annotation) {
+            writeln!(self.out, "{}", annotation)?;
+            return Ok(true);
+        }
+        Ok(false)
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        Ok(self.out.flush()?)
+    }
+}
+
+/// Write TeamCity service messages, which report the diagnostics as inspections.
+pub struct TeamCityReporter<W> {
+    out: W,
+    /// The inspection types that have already been declared.
+    inspection_types: HashSet<String>,
+}
+
+impl<W: Write + Send> TeamCityReporter<W> {
+    pub fn new(out: W) -> Self {
+        TeamCityReporter {
+            out,
+            inspection_types: HashSet::new(),
+        }
+    }
+}
+
+impl<W: Write + Send> Reporter for TeamCityReporter<W> {
+    fn report(&mut self, _line: &str, diagnostic: &Diagnostic) -> Result<bool> {
+        if let Some(ref message) = diagnostic.message {
+            for service_message in teamcity_service_messages(message, &mut self.inspection_types) {
+                writeln!(self.out, "{}", service_message)?;
+            }
+            return Ok(true);
+        }
+        Ok(false)
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        Ok(self.out.flush()?)
+    }
+}
+
+/// Collect the diagnostics, and write them as a single document of the given output kind
+/// (SARIF, JUnit, GitLab Code Quality, HTML, Markdown or CSV) when flushed.
+pub struct DocumentReporter<W> {
+    out: W,
+    kind: OutputKind,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl<W: Write + Send> DocumentReporter<W> {
+    pub fn new(out: W, kind: OutputKind) -> Self {
+        DocumentReporter {
+            out,
+            kind,
+            diagnostics: vec![],
+        }
+    }
+}
+
+impl<W: Write + Send> Reporter for DocumentReporter<W> {
+    fn report(&mut self, _line: &str, diagnostic: &Diagnostic) -> Result<bool> {
+        if diagnostic.message.is_some() {
+            self.diagnostics.push(diagnostic.clone());
+            return Ok(true);
+        }
+        Ok(false)
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        let out = &mut self.out;
+        match self.kind {
+            OutputKind::JUnit => write!(out, "{}", junit_document(&self.diagnostics))?,
+            OutputKind::Html => write!(out, "{}", html_document(&self.diagnostics))?,
+            OutputKind::Markdown => write!(out, "{}", markdown_document(&self.diagnostics))?,
+            OutputKind::Csv => write!(out, "{}", csv_document(&self.diagnostics))?,
+            OutputKind::GitLab => writeln!(
+                out,
+                "{}",
+                serde_json::to_string_pretty(&gitlab_code_quality_report(&self.diagnostics))
+                    .with_context(|| "Failed to serialize the GitLab Code Quality report")?
+            )?,
+            OutputKind::Sarif => {
+                let base =
+                    env::current_dir().with_context(|| "Failed to obtain the current directory")?;
+                let document = sarif_document(&self.diagnostics, &base);
+                writeln!(
+                    out,
+                    "{}",
+                    serde_json::to_string_pretty(&document)
+                        .with_context(|| "Failed to serialize the SARIF document")?
+                )?;
+            }
+            kind => unreachable!("{} is not a document output kind", kind),
+        }
+        self.diagnostics.clear();
+        Ok(out.flush()?)
+    }
+}
+
+/// Keep the reported compiler messages in memory, e.g. to process them further or to check them in
+/// tests.
+#[derive(Clone, Default, Debug)]
+pub struct CapturingReporter {
+    diagnostics: Vec<(String, Diagnostic)>,
+}
+
+impl CapturingReporter {
+    pub fn new() -> Self {
+        CapturingReporter::default()
+    }
+
+    /// Return the reported diagnostics, together with their JSON lines.
+    pub fn diagnostics(&self) -> &[(String, Diagnostic)] {
+        &self.diagnostics
+    }
+
+    pub fn into_diagnostics(self) -> Vec<(String, Diagnostic)> {
+        self.diagnostics
+    }
+}
+
+impl Reporter for CapturingReporter {
+    fn report(&mut self, line: &str, diagnostic: &Diagnostic) -> Result<bool> {
+        if diagnostic.message.is_some() {
+            self.diagnostics
+                .push((line.to_string(), diagnostic.clone()));
+            return Ok(true);
+        }
+        Ok(false)
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// Pass each diagnostic to several reporters, e.g. to write several output formats at once.
+pub struct MultiReporter<'a> {
+    reporters: Vec<Box<dyn Reporter + 'a>>,
+}
+
+impl<'a> MultiReporter<'a> {
+    pub fn new(reporters: Vec<Box<dyn Reporter + 'a>>) -> Self {
+        MultiReporter { reporters }
+    }
+}
+
+impl<'a> Reporter for MultiReporter<'a> {
+    /// Return `true` iff any of the reporters reported the diagnostic, so that it is counted once.
+    fn report(&mut self, line: &str, diagnostic: &Diagnostic) -> Result<bool> {
+        let mut reported = false;
+        for reporter in &mut self.reporters {
+            reported |= reporter.report(line, diagnostic)?;
+        }
+        Ok(reported)
+    }
+
+    fn passthrough(&mut self, line: &str) -> Result<()> {
+        for reporter in &mut self.reporters {
+            reporter.passthrough(line)?;
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        for reporter in &mut self.reporters {
+            reporter.flush()?;
+        }
+        Ok(())
+    }
+}
+
+/// Return a copy of the JSON line and of the diagnostic, with their level replaced by `level`.
+pub(crate) fn override_level(
+    json_line: &str,
+    diagnostic: &Diagnostic,
+    level: Level,
+) -> (String, Diagnostic) {
+    let mut diagnostic = diagnostic.clone();
+    let mut json_line = json_line.to_string();
+    if let Some(ref mut message) = diagnostic.message {
+        message.rendered = override_rendered_level(&message.rendered, message.level, level);
+        if let Some(ref mut rendered) = diagnostic.rendered {
+            *rendered = override_rendered_level(rendered, message.level, level);
+        }
+        message.level = level;
+        if let Ok(mut json) = serde_json::from_str::<Value>(&json_line) {
+            json["message"]["level"] = json!(level.as_str());
+            json["message"]["rendered"] = json!(message.rendered);
+            if let Some(ref rendered) = diagnostic.rendered {
+                json["rendered"] = json!(rendered);
+            }
+            json_line = json.to_string();
+        }
+    }
+    (json_line, diagnostic)
+}
+
+/// Return a copy of the JSON line and of the diagnostic, without the ANSI colors of their rendered
+/// messages.
+pub fn strip_colors(json_line: &str, diagnostic: &Diagnostic) -> (String, Diagnostic) {
+    let mut diagnostic = diagnostic.clone();
+    let mut json_line = json_line.to_string();
+    if let Some(ref mut message) = diagnostic.message {
+        message.rendered = strip_ansi_escapes(&message.rendered).into_owned();
+    }
+    if let Some(ref mut rendered) = diagnostic.rendered {
+        *rendered = strip_ansi_escapes(rendered).into_owned();
+    }
+    if let Ok(mut json) = serde_json::from_str::<Value>(&json_line) {
+        if let Some(ref message) = diagnostic.message {
+            json["message"]["rendered"] = json!(message.rendered);
+        }
+        if let Some(ref rendered) = diagnostic.rendered {
+            json["rendered"] = json!(rendered);
+        }
+        json_line = json.to_string();
+    }
+    (json_line, diagnostic)
+}
+
+/// Replace the level at the beginning of a rendered message, together with its ANSI color.
+fn override_rendered_level(rendered: &str, from: Level, to: Level) -> String {
+    lazy_static! {
+        static ref RE: Regex = Regex::new(r"^(?P<colors>(\x1b\[[0-9;]*m)*)(?P<level>[a-z: -]+)")
+            .expect("Failed to parse regex");
+    }
+    let level_color = |level| match level {
+        Level::Warning => Some("\x1b[33m"),
+        Level::Error | Level::Ice => Some("\x1b[91m"),
+        _ => None,
+    };
+    if let Some(cap) = RE.captures(rendered) {
+        let level_match = cap.name("level").unwrap();
+        if let Some(rest) = level_match.as_str().strip_prefix(from.as_str()) {
+            let mut colors = cap["colors"].to_string();
+            if let (Some(from_color), Some(to_color)) = (level_color(from), level_color(to)) {
+                colors = colors.replace(from_color, to_color);
+            }
+            return format!(
+                "{}{}{}{}",
+                colors,
+                to.as_str(),
+                rest,
+                &rendered[level_match.end()..]
+            );
+        }
+    }
+    rendered.to_string()
+}
+
+/// Return the GitHub Actions workflow command that annotates the primary span of the message.
+fn github_annotation(message: &Message) -> Option<String> {
+    let primary_span = message.primary_span()?;
+    let message_kind = match message.level {
+        Level::Help => "debug",
+        Level::Note => "debug",
+        Level::FailureNote => "debug",
+        Level::Warning => "warning",
+        Level::Error | Level::Ice => "error",
+    };
+    Some(format!(
+        "::{message_kind} file={name},line={line},endLine={end_line},col={col},endColumn={end_col}::{message}",
+        message_kind = message_kind,
+        name = escape_github_property(&primary_span.file_name),
+        line = primary_span.line_start,
+        end_line = primary_span.line_end,
+        col = primary_span.column_start,
+        end_col = primary_span.column_end,
+        message = escape_github_message(&strip_ansi_escapes(&message.rendered)),
+    ))
+}
+
+fn escape_github_message(message: &str) -> String {
+    message
+        .replace("%", "%25")
+        .replace("\r", "%0D")
+        .replace("\n", "%0A")
+}
+
+fn escape_github_property(property: &str) -> String {
+    escape_github_message(property)
+        .replace(":", "%3A")
+        .replace(",", "%2C")
+}
+
+/// Return the TeamCity service messages that report the message as an inspection, declaring its
+/// inspection type if it is not in `declared_types` yet. Errors are also reported as build
+/// problems.
+fn teamcity_service_messages(
+    message: &Message,
+    declared_types: &mut HashSet<String>,
+) -> Vec<String> {
+    let mut service_messages = vec![];
+    let lint_name = lint_name(message);
+    if declared_types.insert(lint_name.to_string()) {
+        service_messages.push(format!(
+            "##teamcity[inspectionType id='{id}' name='{id}' description='{id}' category='{category}']",
+            id = escape_teamcity_value(lint_name),
+            category = message.level.as_str(),
+        ));
+    }
+    let severity = match message.level {
+        Level::Help | Level::Note | Level::FailureNote => "INFO",
+        Level::Warning => "WARNING",
+        Level::Error | Level::Ice => "ERROR",
+    };
+    let (file, line) = message
+        .primary_span()
+        .map(|span| (span.file_name.as_str(), span.line_start))
+        .unwrap_or_default();
+    service_messages.push(format!(
+        "##teamcity[inspection typeId='{type_id}' message='{message}' file='{file}' line='{line}' SEVERITY='{severity}']",
+        type_id = escape_teamcity_value(lint_name),
+        message = escape_teamcity_value(&message.message),
+        file = escape_teamcity_value(file),
+        line = line,
+        severity = severity,
+    ));
+    if matches!(message.level, Level::Error | Level::Ice) {
+        service_messages.push(format!(
+            "##teamcity[buildProblem description='{}']",
+            escape_teamcity_value(&format!("{}:{}: {}", file, line, message.message)),
+        ));
+    }
+    service_messages
+}
+
+fn escape_teamcity_value(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '|' => escaped.push_str("||"),
+            '\'' => escaped.push_str("|'"),
+            '[' => escaped.push_str("|["),
+            ']' => escaped.push_str("|]"),
+            '\n' => escaped.push_str("|n"),
+            '\r' => escaped.push_str("|r"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Return the lint name or error code of the message, falling back to its level.
+fn lint_name(message: &Message) -> &str {
+    match message.code {
+        Some(ref code) => code.code.as_str(),
+        None => message.level.as_str(),
+    }
+}
+
+/// Remove the ANSI escape sequences used to color the rendered messages.
+fn strip_ansi_escapes(text: &str) -> Cow<'_, str> {
+    lazy_static! {
+        static ref RE: Regex = Regex::new(r"\x1b\[[0-9;]*[A-Za-z]").expect("Failed to parse regex");
+    }
+    RE.replace_all(text, "")
+}
+
+/// Escape the text to be included in XML or HTML documents.
+fn escape_xml(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            // Control characters are not allowed in XML 1.0
+            '\t' | '\n' | '\r' => escaped.push(c),
+            c if c.is_control() => {}
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Build a JUnit XML document, with a test suite for each file and a failed test case for each
+/// diagnostic.
+fn junit_document(diagnostics: &[Diagnostic]) -> String {
+    let mut messages_by_file = BTreeMap::new();
+    for message in diagnostics.iter().filter_map(|d| d.message.as_ref()) {
+        let file_name = message
+            .primary_span()
+            .map(|span| span.file_name.as_str())
+            .unwrap_or_default();
+        messages_by_file
+            .entry(file_name)
+            .or_insert_with(Vec::new)
+            .push(message);
+    }
+
+    let mut document = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    document.push_str(&format!(
+        "<testsuites name=\"{}\" tests=\"{count}\" failures=\"{count}\">\n",
+        env!("CARGO_PKG_NAME"),
+        count = messages_by_file.values().map(Vec::len).sum::<usize>(),
+    ));
+    for (file_name, messages) in messages_by_file {
+        document.push_str(&format!(
+            "  <testsuite name=\"{name}\" tests=\"{count}\" failures=\"{count}\">\n",
+            name = escape_xml(file_name),
+            count = messages.len(),
+        ));
+        for message in messages {
+            let lint_name = lint_name(message);
+            let line = message
+                .primary_span()
+                .map(|span| span.line_start)
+                .unwrap_or_default();
+            document.push_str(&format!(
+                "    <testcase name=\"{name}\" classname=\"{classname}\">\n",
+                name = escape_xml(&format!("{} at {}:{}", lint_name, file_name, line)),
+                classname = escape_xml(file_name),
+            ));
+            document.push_str(&format!(
+                "      <failure message=\"{message}\" type=\"{level}\">{rendered}</failure>\n",
+                message = escape_xml(&message.message),
+                level = message.level.as_str(),
+                rendered = escape_xml(&strip_ansi_escapes(&message.rendered)),
+            ));
+            document.push_str("    </testcase>\n");
+        }
+        document.push_str("  </testsuite>\n");
+    }
+    document.push_str("</testsuites>\n");
+    document
+}
+
+const HTML_STYLE: &str = "
+body { font-family: -apple-system, BlinkMacSystemFont, \"Segoe UI\", Helvetica, Arial, sans-serif; margin: 2em; color: #24292f; }
+table { border-collapse: collapse; width: 100%; }
+th, td { border: 1px solid #d0d7de; padding: 0.5em; text-align: left; vertical-align: top; }
+th { background: #f6f8fa; }
+pre { margin: 0; white-space: pre-wrap; font-size: 0.9em; }
+.badge { border-radius: 1em; padding: 0.1em 0.6em; color: #fff; font-size: 0.85em; font-weight: bold; }
+.badge-error { background: #cf222e; }
+.badge-warning { background: #bf8700; }
+.badge-note, .badge-help { background: #6e7781; }
+";
+
+/// Build a self-contained HTML report, with a row for each diagnostic.
+fn html_document(diagnostics: &[Diagnostic]) -> String {
+    let mut document = String::from("<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n");
+    document.push_str(&format!(
+        "<title>{} report</title>\n<style>{}</style>\n</head>\n<body>\n",
+        env!("CARGO_PKG_NAME"),
+        HTML_STYLE
+    ));
+    let messages: Vec<_> = diagnostics
+        .iter()
+        .filter_map(|d| d.message.as_ref())
+        .collect();
+    document.push_str(&format!(
+        "<h1>{} diagnostic(s) in the changed lines</h1>\n",
+        messages.len()
+    ));
+    document.push_str(
+        "<table>\n<tr><th>File</th><th>Line</th><th>Severity</th><th>Message</th></tr>\n",
+    );
+    for message in messages {
+        let (file, line) = message
+            .primary_span()
+            .map(|span| (span.file_name.as_str(), span.line_start))
+            .unwrap_or_default();
+        document.push_str(&format!(
+            "<tr><td>{file}</td><td>{line}</td><td><span class=\"badge badge-{level}\">{level}</span></td><td><pre>{rendered}</pre></td></tr>\n",
+            file = escape_xml(file),
+            line = line,
+            level = message.level.as_str(),
+            rendered = escape_xml(&strip_ansi_escapes(&message.rendered)),
+        ));
+    }
+    document.push_str("</table>\n</body>\n</html>\n");
+    document
+}
+
+/// Build a GitHub-flavored Markdown document, with a section for each diagnostic and a summary
+/// of the number of diagnostics by level.
+fn markdown_document(diagnostics: &[Diagnostic]) -> String {
+    const LEVELS: [Level; 6] = [
+        Level::Ice,
+        Level::Error,
+        Level::Warning,
+        Level::FailureNote,
+        Level::Note,
+        Level::Help,
+    ];
+    let mut document = String::new();
+    let mut counts = [0; LEVELS.len()];
+    for message in diagnostics.iter().filter_map(|d| d.message.as_ref()) {
+        let heading = match message.level {
+            Level::Help => "💡 Help",
+            Level::Note => "ℹ️ Note",
+            Level::FailureNote => "ℹ️ Failure note",
+            Level::Warning => "⚠️ Warning",
+            Level::Error => "❌ Error",
+            Level::Ice => "💥 Internal compiler error",
+        };
+        counts[LEVELS.iter().position(|&l| l == message.level).unwrap()] += 1;
+        let rendered = strip_ansi_escapes(&message.rendered);
+        // The fence must be longer than any sequence of backticks in the message
+        let fence = "`".repeat(
+            3.max(
+                rendered
+                    .split(|c| c != '`')
+                    .map(str::len)
+                    .max()
+                    .unwrap_or_default()
+                    + 1,
+            ),
+        );
+        document.push_str(&format!(
+            "### {}\n\n{fence}\n{}\n{fence}\n\n",
+            heading,
+            rendered.trim_end(),
+            fence = fence,
+        ));
+        if let Some(span) = message.primary_span() {
+            document.push_str(&format!(
+                "> File: {}, line {}\n\n",
+                span.file_name, span.line_start
+            ));
+        }
+    }
+    document.push_str("## Summary\n\n| Level | Count |\n| --- | ---: |\n");
+    for (level, count) in LEVELS.iter().zip(&counts) {
+        document.push_str(&format!("| {} | {} |\n", level.as_str(), count));
+    }
+    document
+}
+
+/// Build a CSV document as specified by RFC 4180, with a header and a row for each diagnostic.
+fn csv_document(diagnostics: &[Diagnostic]) -> String {
+    let mut document = String::from("severity,file,line_start,line_end,lint_code,message\r\n");
+    for diagnostic in diagnostics {
+        let message = match diagnostic.message {
+            Some(ref message) => message,
+            None => continue,
+        };
+        let (file, line_start, line_end) = diagnostic
+            .primary_span()
+            .map(|span| (span.file_name.as_str(), span.line_start, span.line_end))
+            .unwrap_or_default();
+        let lint_code = diagnostic.lint_code().unwrap_or_default();
+        document.push_str(&format!(
+            "{},{},{},{},{},{}\r\n",
+            message.level.as_str(),
+            escape_csv_field(file),
+            line_start,
+            line_end,
+            escape_csv_field(lint_code),
+            escape_csv_field(&message.message),
+        ));
+    }
+    document
+}
+
+fn escape_csv_field(field: &str) -> Cow<'_, str> {
+    if field.contains(&[',', '"', '\r', '\n'][..]) {
+        Cow::Owned(format!("\"{}\"", field.replace('"', "\"\"")))
+    } else {
+        Cow::Borrowed(field)
+    }
+}
+
+/// Build a GitLab Code Quality report. The fingerprint of each issue is derived from its file,
+/// line and lint name, so that it is stable across runs.
+fn gitlab_code_quality_report(diagnostics: &[Diagnostic]) -> Value {
+    let issues: Vec<Value> = diagnostics
+        .iter()
+        .filter_map(|diagnostic| diagnostic.message.as_ref())
+        .map(|message| {
+            let severity = match message.level {
+                Level::Help | Level::Note | Level::FailureNote => "info",
+                Level::Warning => "major",
+                Level::Error => "critical",
+                Level::Ice => "blocker",
+            };
+            let (path, line) = message
+                .primary_span()
+                .map(|span| (span.file_name.as_str(), span.line_start))
+                .unwrap_or_default();
+            let lint_name = lint_name(message);
+            let fingerprint = Sha256::digest(format!("{}:{}:{}", path, line, lint_name).as_bytes())
+                .iter()
+                .map(|byte| format!("{:02x}", byte))
+                .collect::<String>();
+            json!({
+                "description": message.message,
+                "check_name": lint_name,
+                "fingerprint": fingerprint,
+                "severity": severity,
+                "location": {
+                    "path": path,
+                    "lines": { "begin": line },
+                },
+            })
+        })
+        .collect();
+    Value::Array(issues)
+}
+
+/// Build a SARIF 2.1.0 document. Relative paths of the spans are resolved from `base`.
+fn sarif_document(diagnostics: &[Diagnostic], base: &Path) -> Value {
+    let results: Vec<Value> = diagnostics
+        .iter()
+        .filter_map(|diagnostic| diagnostic.message.as_ref())
+        .map(|message| {
+            let level = match message.level {
+                Level::Help | Level::Note | Level::FailureNote => "note",
+                Level::Warning => "warning",
+                Level::Error | Level::Ice => "error",
+            };
+            let locations: Vec<Value> = message
+                .primary_span()
+                .into_iter()
+                .map(|span| {
+                    json!({
+                        "physicalLocation": {
+                            "artifactLocation": {
+                                "uri": file_uri(&base.join(&span.file_name)),
+                            },
+                            "region": {
+                                "startLine": span.line_start,
+                                "startColumn": span.column_start,
+                                "endLine": span.line_end,
+                                "endColumn": span.column_end,
+                            },
+                        },
+                    })
+                })
+                .collect();
+            let mut result = json!({
+                "level": level,
+                "message": { "text": message.message },
+                "locations": locations,
+            });
+            if let Some(ref code) = message.code {
+                result["ruleId"] = json!(code.code);
+            }
+            result
+        })
+        .collect();
+
+    json!({
+        "$schema": "https://json.schemastore.org/sarif-2.1.0.json",
+        "version": "2.1.0",
+        "runs": [{
+            "tool": {
+                "driver": {
+                    "name": env!("CARGO_PKG_NAME"),
+                    "version": crate_version!(),
+                    "informationUri": env!("CARGO_PKG_HOMEPAGE"),
+                },
+            },
+            "results": results,
+        }],
+    })
+}
+
+/// Return the `file://` URI of an absolute path, percent-encoding the reserved characters.
+fn file_uri(path: &Path) -> String {
+    let path = path.to_string_lossy().replace('\\', "/");
+    let mut uri = String::from("file://");
+    if !path.starts_with('/') {
+        // Windows paths, e.g. `C:/foo`
+        uri.push('/');
+    }
+    for byte in path.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' | b'/' | b':' => {
+                uri.push(byte as char)
+            }
+            _ => uri.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    uri
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_override_rendered_level() {
+        assert_eq!(
+            override_rendered_level(
+                "\x1b[1m\x1b[33mwarning\x1b[0m\x1b[1m: unused variable: `x`\x1b[0m\n",
+                Level::Warning,
+                Level::Error
+            ),
+            "\x1b[1m\x1b[91merror\x1b[0m\x1b[1m: unused variable: `x`\x1b[0m\n"
+        );
+        assert_eq!(
+            override_rendered_level(
+                "warning: unneeded `return` statement\n",
+                Level::Warning,
+                Level::Error
+            ),
+            "error: unneeded `return` statement\n"
+        );
+        assert_eq!(
+            override_rendered_level("note: unrelated\n", Level::Warning, Level::Error),
+            "note: unrelated\n"
+        );
+    }
+
+    #[test]
+    fn test_report_top_level_rendered() {
+        let json_line = r#"{
+            "message": {
+                "rendered": "warning: unused variable: `x`",
+                "message": "unused variable: `x`",
+                "code": null,
+                "level": "warning",
+                "spans": []
+            },
+            "rendered": "warning: unused variable: `x`\n --> src/lib.rs:2:9\n"
+        }"#;
+        let diagnostic: Diagnostic = serde_json::from_str(json_line).unwrap();
+        let mut out = Vec::new();
+        let reported = RenderedReporter::new(&mut out)
+            .report(json_line, &diagnostic)
+            .unwrap();
+        assert!(reported);
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "warning: unused variable: `x`\n --> src/lib.rs:2:9\n\n"
+        );
+    }
+
+    #[test]
+    fn test_multi_reporter() {
+        let json_line = r#"{"message":{"rendered":"warning: unused variable: `x`","message":"unused variable: `x`","code":null,"level":"warning","spans":[]}}"#;
+        let diagnostic: Diagnostic = serde_json::from_str(json_line).unwrap();
+        let (mut first, mut second, mut github) = (Vec::new(), Vec::new(), Vec::new());
+        {
+            let mut reporter = MultiReporter::new(vec![
+                OutputKind::Json.create_reporter(&mut first),
+                OutputKind::Json.create_reporter(&mut second),
+                // Reports nothing, since the message has no span to annotate
+                OutputKind::GitHub.create_reporter(&mut github),
+            ]);
+            assert!(reporter.report(json_line, &diagnostic).unwrap());
+            reporter.flush().unwrap();
+        }
+        assert_eq!(
+            String::from_utf8(first.clone()).unwrap(),
+            format!("{}\n", json_line)
+        );
+        assert_eq!(first, second);
+        assert!(github.is_empty());
+    }
+
+    #[test]
+    fn test_capturing_reporter() {
+        let json_line = r#"{"message":{"rendered":"warning: unused variable: `x`","message":"unused variable: `x`","code":null,"level":"warning","spans":[]}}"#;
+        let diagnostic: Diagnostic = serde_json::from_str(json_line).unwrap();
+        let build_finished = r#"{"reason":"build-finished","success":true}"#;
+        let mut reporter = CapturingReporter::new();
+        assert!(reporter.report(json_line, &diagnostic).unwrap());
+        assert!(!reporter
+            .report(
+                build_finished,
+                &serde_json::from_str(build_finished).unwrap()
+            )
+            .unwrap());
+        assert_eq!(
+            reporter.into_diagnostics(),
+            vec![(json_line.to_string(), diagnostic)]
+        );
+    }
+
+    #[test]
+    fn test_github_annotation() {
+        let message: Message = serde_json::from_str(
+            r#"{
+                "rendered": "\u001b[33mwarning\u001b[0m: unused variable: `x`\n --> src/lib.rs:2:9\n\n",
+                "message": "unused variable: `x`",
+                "code": { "code": "unused_variables", "explanation": null },
+                "level": "warning",
+                "spans": [{
+                    "file_name": "src/a,b.rs",
+                    "line_start": 2,
+                    "line_end": 3,
+                    "column_start": 9,
+                    "column_end": 10,
+                    "is_primary": true
+                }]
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(
+            github_annotation(&message).unwrap(),
+            "::warning file=src/a%2Cb.rs,line=2,endLine=3,col=9,endColumn=10::\
+             warning: unused variable: `x`%0A --> src/lib.rs:2:9%0A%0A"
+        );
+    }
+
+    #[test]
+    fn test_teamcity_service_messages() {
+        let message: Message = serde_json::from_str(
+            r#"{
+                "rendered": "error: unexpected token",
+                "message": "expected one of `|` or `]`\nfound 'x'\r",
+                "code": { "code": "E0001", "explanation": null },
+                "level": "error",
+                "spans": [{
+                    "file_name": "src/lib.rs",
+                    "line_start": 7,
+                    "line_end": 7,
+                    "column_start": 1,
+                    "column_end": 2,
+                    "is_primary": true
+                }]
+            }"#,
+        )
+        .unwrap();
+        let mut declared_types = HashSet::new();
+        assert_eq!(
+            teamcity_service_messages(&message, &mut declared_types),
+            vec![
+                "##teamcity[inspectionType id='E0001' name='E0001' description='E0001' category='error']",
+                "##teamcity[inspection typeId='E0001' message='expected one of `||` or `|]`|nfound |'x|'|r' file='src/lib.rs' line='7' SEVERITY='ERROR']",
+                "##teamcity[buildProblem description='src/lib.rs:7: expected one of `||` or `|]`|nfound |'x|'|r']",
+            ]
+        );
+        // The inspection type is declared only once
+        assert_eq!(
+            teamcity_service_messages(&message, &mut declared_types).len(),
+            2
+        );
+    }
+
+    #[test]
+    fn test_html_document() {
+        let diagnostic: Diagnostic = serde_json::from_str(
+            r#"{
+                "reason": "compiler-message",
+                "message": {
+                    "rendered": "\u001b[1m\u001b[33mwarning\u001b[0m: this comparison `a < b` is useless",
+                    "message": "this comparison `a < b` is useless",
+                    "code": null,
+                    "level": "warning",
+                    "spans": [{
+                        "file_name": "src/lib.rs",
+                        "line_start": 12,
+                        "line_end": 12,
+                        "column_start": 1,
+                        "column_end": 2,
+                        "is_primary": true
+                    }]
+                }
+            }"#,
+        )
+        .unwrap();
+        let document = html_document(&[diagnostic]);
+        assert!(document.starts_with("<!DOCTYPE html>"));
+        assert!(document.contains(
+            "<tr><td>src/lib.rs</td><td>12</td>\
+             <td><span class=\"badge badge-warning\">warning</span></td>\
+             <td><pre>warning: this comparison `a &lt; b` is useless</pre></td></tr>"
+        ));
+    }
+
+    #[test]
+    fn test_markdown_document() {
+        let diagnostic: Diagnostic = serde_json::from_str(
+            r#"{
+                "reason": "compiler-message",
+                "message": {
+                    "rendered": "error[E0308]: mismatched types\n```\n",
+                    "message": "mismatched types",
+                    "code": { "code": "E0308", "explanation": null },
+                    "level": "error",
+                    "spans": [{
+                        "file_name": "src/main.rs",
+                        "line_start": 3,
+                        "line_end": 3,
+                        "column_start": 18,
+                        "column_end": 21,
+                        "is_primary": true
+                    }]
+                }
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(
+            markdown_document(&[diagnostic]),
+            indoc::indoc! {"
+                ### ❌ Error
+
+                ````
+                error[E0308]: mismatched types
+                ```
+                ````
+
+                > File: src/main.rs, line 3
+
+                ## Summary
+
+                | Level | Count |
+                | --- | ---: |
+                | error: internal compiler error | 0 |
+                | error | 1 |
+                | warning | 0 |
+                | failure-note | 0 |
+                | note | 0 |
+                | help | 0 |
+            "}
+        );
+    }
+
+    #[test]
+    fn test_escape_csv_field() {
+        assert_eq!(escape_csv_field("unused variable"), "unused variable");
+        assert_eq!(escape_csv_field("a, b"), "\"a, b\"");
+        assert_eq!(escape_csv_field("use `\"a\"`"), "\"use `\"\"a\"\"`\"");
+        assert_eq!(escape_csv_field("one\ntwo"), "\"one\ntwo\"");
+    }
+
+    #[test]
+    fn test_gitlab_code_quality_report() {
+        let diagnostic: Diagnostic = serde_json::from_str(
+            r#"{
+                "reason": "compiler-message",
+                "message": {
+                    "rendered": "error[E0308]: mismatched types",
+                    "message": "mismatched types",
+                    "code": { "code": "E0308", "explanation": null },
+                    "level": "error",
+                    "spans": [{
+                        "file_name": "src/main.rs",
+                        "line_start": 3,
+                        "line_end": 3,
+                        "column_start": 18,
+                        "column_end": 21,
+                        "is_primary": true
+                    }]
+                }
+            }"#,
+        )
+        .unwrap();
+        let report = gitlab_code_quality_report(&[diagnostic.clone(), diagnostic]);
+        let issue = &report[0];
+        assert_eq!(issue["description"], "mismatched types");
+        assert_eq!(issue["severity"], "critical");
+        assert_eq!(issue["location"]["path"], "src/main.rs");
+        assert_eq!(issue["location"]["lines"]["begin"], 3);
+        assert_eq!(
+            issue["fingerprint"],
+            "47339effb22aea8921e8bcda385ba2725d67c424c1e809f7b4f0ed21cf3d8035"
+        );
+        assert_eq!(issue["fingerprint"], report[1]["fingerprint"]);
+    }
+
+    #[test]
+    fn test_sarif_document() {
+        let diagnostic: Diagnostic = serde_json::from_str(
+            r#"{
+                "reason": "compiler-message",
+                "message": {
+                    "rendered": "warning: unused variable: `x`",
+                    "message": "unused variable: `x`",
+                    "code": { "code": "unused_variables", "explanation": null },
+                    "level": "warning",
+                    "spans": [{
+                        "file_name": "src/my lib.rs",
+                        "line_start": 2,
+                        "line_end": 2,
+                        "column_start": 9,
+                        "column_end": 10,
+                        "is_primary": true
+                    }]
+                }
+            }"#,
+        )
+        .unwrap();
+        let document = sarif_document(&[diagnostic], Path::new("/home/user/project"));
+        let result = &document["runs"][0]["results"][0];
+        assert_eq!(document["version"], "2.1.0");
+        assert_eq!(result["ruleId"], "unused_variables");
+        assert_eq!(result["level"], "warning");
+        assert_eq!(result["message"]["text"], "unused variable: `x`");
+        let location = &result["locations"][0]["physicalLocation"];
+        assert_eq!(
+            location["artifactLocation"]["uri"],
+            "file:///home/user/project/src/my%20lib.rs"
+        );
+        assert_eq!(location["region"]["startLine"], 2);
+        assert_eq!(location["region"]["startColumn"], 9);
+    }
+}
@@ -0,0 +1,22 @@
+use crate::diagnostics::Diagnostic;
+use anyhow::Result;
+
+/// Writes the diagnostics that should be reported in some output format.
+///
+/// The crate implements a reporter per [`OutputKind`](crate::OutputKind), but any other one can
+/// be passed to [`process_stream_with_reporter`](crate::process_stream_with_reporter).
+pub trait Reporter: Send {
+    /// Report a diagnostic, given together with the JSON line it was parsed from. Return `false`
+    /// iff there was nothing to report, e.g. because the line is not a compiler message.
+    fn report(&mut self, line: &str, diagnostic: &Diagnostic) -> Result<bool>;
+
+    /// Write a JSON line of cargo that is not a diagnostic, e.g. `compiler-artifact`, unchanged.
+    /// By default, the line is dropped, since only the JSON output can contain it.
+    fn passthrough(&mut self, _line: &str) -> Result<()> {
+        Ok(())
+    }
+
+    /// Write whatever is left once all the diagnostics are reported, such as the documents of the
+    /// output formats that cannot be streamed.
+    fn flush(&mut self) -> Result<()>;
+}
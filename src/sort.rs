@@ -0,0 +1,180 @@
+use crate::diagnostics::Diagnostic;
+use clap::arg_enum;
+use std::cmp::Reverse;
+
+arg_enum! {
+    #[derive(Copy, Clone, PartialEq, Eq, Debug)]
+    pub enum SortKey {
+        File,
+        Line,
+        Severity,
+        Lint,
+    }
+}
+
+impl SortKey {
+    /// Sort the diagnostics, given together with their JSON lines, by the key. The sort is stable,
+    /// so the diagnostics that compare equal keep the order of the compiler. The lines without a
+    /// message, such as cargo's `build-finished`, come last.
+    pub fn sort(self, diagnostics: &mut [(String, Diagnostic)]) {
+        match self {
+            SortKey::File => diagnostics.sort_by(|(_, a), (_, b)| {
+                (a.message.is_none(), location(a)).cmp(&(b.message.is_none(), location(b)))
+            }),
+            SortKey::Line => diagnostics.sort_by(|(_, a), (_, b)| {
+                let ((file_a, line_a), (file_b, line_b)) = (location(a), location(b));
+                (a.message.is_none(), line_a, file_a).cmp(&(b.message.is_none(), line_b, file_b))
+            }),
+            // Errors first, then warnings, then the notes
+            SortKey::Severity => diagnostics.sort_by(|(_, a), (_, b)| {
+                let level = |diagnostic: &Diagnostic| {
+                    Reverse(diagnostic.message.as_ref().map(|message| message.level))
+                };
+                (a.message.is_none(), level(a), location(a)).cmp(&(
+                    b.message.is_none(),
+                    level(b),
+                    location(b),
+                ))
+            }),
+            // The diagnostics without a lint code come after the others
+            SortKey::Lint => diagnostics.sort_by(|(_, a), (_, b)| {
+                (
+                    a.message.is_none(),
+                    a.lint_code().is_none(),
+                    a.lint_code(),
+                    location(a),
+                )
+                    .cmp(&(
+                        b.message.is_none(),
+                        b.lint_code().is_none(),
+                        b.lint_code(),
+                        location(b),
+                    ))
+            }),
+        }
+    }
+}
+
+/// The file of a group of diagnostics, if they have a primary span, and the diagnostics with their
+/// JSON lines.
+pub(crate) type FileGroup = (Option<String>, Vec<(String, Diagnostic)>);
+
+/// Group the diagnostics by the file of their primary span, in the order of the first diagnostic
+/// of each file. The diagnostics without a primary span come last, in a group without a file.
+pub(crate) fn group_by_file(diagnostics: Vec<(String, Diagnostic)>) -> Vec<FileGroup> {
+    let mut groups: Vec<FileGroup> = vec![];
+    let mut other = vec![];
+    for (json_line, diagnostic) in diagnostics {
+        let file_name = match diagnostic.primary_span() {
+            Some(span) => span.file_name.clone(),
+            None => {
+                other.push((json_line, diagnostic));
+                continue;
+            }
+        };
+        match groups
+            .iter_mut()
+            .find(|(group, _)| group.as_deref() == Some(file_name.as_str()))
+        {
+            Some((_, group)) => group.push((json_line, diagnostic)),
+            None => groups.push((Some(file_name), vec![(json_line, diagnostic)])),
+        }
+    }
+    if !other.is_empty() {
+        groups.push((None, other));
+    }
+    groups
+}
+
+/// Return the file and the first line of the primary span of the diagnostic, if there is any.
+fn location(diagnostic: &Diagnostic) -> (Option<&str>, Option<usize>) {
+    let span = diagnostic.primary_span();
+    (
+        span.map(|span| span.file_name.as_str()),
+        span.map(|span| span.line_start),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn diagnostic(file_name: &str, line: usize, level: &str, code: &str) -> (String, Diagnostic) {
+        let json_line = serde_json::json!({
+            "message": {
+                "message": "",
+                "code": { "code": code, "explanation": null },
+                "level": level,
+                "rendered": "",
+                "spans": [{
+                    "file_name": file_name,
+                    "line_start": line,
+                    "line_end": line,
+                    "column_start": 1,
+                    "column_end": 2,
+                    "is_primary": true,
+                }],
+            },
+        })
+        .to_string();
+        let diagnostic = serde_json::from_str(&json_line).unwrap();
+        (json_line, diagnostic)
+    }
+
+    #[test]
+    fn test_sort() {
+        let finished = r#"{"reason":"build-finished","success":false}"#.to_string();
+        let mut diagnostics = vec![
+            (finished.clone(), serde_json::from_str(&finished).unwrap()),
+            diagnostic("src/main.rs", 3, "warning", "clippy::needless_return"),
+            diagnostic("src/lib.rs", 10, "error", "E0308"),
+            diagnostic("src/lib.rs", 2, "warning", "unused_variables"),
+        ];
+        let order = |diagnostics: &[(String, Diagnostic)]| {
+            diagnostics
+                .iter()
+                .map(|(_, diagnostic)| {
+                    diagnostic
+                        .primary_span()
+                        .map(|span| format!("{}:{}", span.file_name, span.line_start))
+                        .unwrap_or_default()
+                })
+                .collect::<Vec<_>>()
+        };
+
+        SortKey::File.sort(&mut diagnostics);
+        assert_eq!(
+            order(&diagnostics),
+            ["src/lib.rs:2", "src/lib.rs:10", "src/main.rs:3", ""]
+        );
+        SortKey::Line.sort(&mut diagnostics);
+        assert_eq!(
+            order(&diagnostics),
+            ["src/lib.rs:2", "src/main.rs:3", "src/lib.rs:10", ""]
+        );
+        SortKey::Severity.sort(&mut diagnostics);
+        assert_eq!(
+            order(&diagnostics),
+            ["src/lib.rs:10", "src/lib.rs:2", "src/main.rs:3", ""]
+        );
+        SortKey::Lint.sort(&mut diagnostics);
+        assert_eq!(
+            order(&diagnostics),
+            ["src/lib.rs:10", "src/main.rs:3", "src/lib.rs:2", ""]
+        );
+
+        let groups = group_by_file(diagnostics);
+        let files: Vec<_> = groups
+            .iter()
+            .map(|(file_name, group)| (file_name.as_deref(), order(group).join(" ")))
+            .collect();
+        assert_eq!(
+            files,
+            [
+                (Some("src/lib.rs"), "src/lib.rs:10 src/lib.rs:2".to_string()),
+                (Some("src/main.rs"), "src/main.rs:3".to_string()),
+                (None, String::new()),
+            ]
+        );
+    }
+}
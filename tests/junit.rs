@@ -0,0 +1,75 @@
+use std::io::Write;
+use std::process::{Command, Stdio};
+use std::{env, fs};
+
+const DIFF: &str = "\
++++ b/src/lib.rs
+@@ -1,0 +2,3 @@
++++ b/src/main.rs
+@@ -9 +10 @@
+";
+
+const DIAGNOSTICS: &str = r#"{"reason":"compiler-message","message":{"rendered":"\u001b[33mwarning\u001b[0m: comparison is useless: `a < b && b > c`\n","message":"comparison is useless: `a < b && b > c`","code":{"code":"clippy::nonminimal_bool","explanation":null},"level":"warning","spans":[{"file_name":"src/lib.rs","line_start":3,"line_end":3,"column_start":5,"column_end":20,"is_primary":true}]}}
+{"reason":"compiler-message","message":{"rendered":"error[E0308]: mismatched types\n","message":"mismatched types","code":{"code":"E0308","explanation":null},"level":"error","spans":[{"file_name":"src/main.rs","line_start":10,"line_end":10,"column_start":18,"column_end":21,"is_primary":true}]}}
+{"reason":"compiler-message","message":{"rendered":"warning: unused variable: `x`\n","message":"unused variable: `x`","code":{"code":"unused_variables","explanation":null},"level":"warning","spans":[{"file_name":"src/main.rs","line_start":30,"line_end":30,"column_start":9,"column_end":10,"is_primary":true}]}}
+"#;
+
+#[test]
+fn test_junit_output_is_well_formed() {
+    let diff_path = env::temp_dir().join(format!(
+        "cargo-diff-tools-junit-{}.diff",
+        std::process::id()
+    ));
+    fs::write(&diff_path, DIFF).unwrap();
+
+    let mut child = Command::new(env!("CARGO_BIN_EXE_filter-by-diff"))
+        .arg("--output=junit")
+        .arg("--diff-file")
+        .arg(&diff_path)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(DIAGNOSTICS.as_bytes())
+        .unwrap();
+    let output = child.wait_with_output().unwrap();
+    fs::remove_file(&diff_path).unwrap();
+    assert!(!output.status.success());
+
+    let xml = String::from_utf8(output.stdout).unwrap();
+    let document = roxmltree::Document::parse(&xml).unwrap();
+    let root = document.root_element();
+    assert_eq!(root.tag_name().name(), "testsuites");
+    assert_eq!(root.attribute("failures"), Some("2"));
+
+    let suites: Vec<_> = root
+        .children()
+        .filter(|n| n.has_tag_name("testsuite"))
+        .collect();
+    assert_eq!(suites.len(), 2);
+    assert_eq!(suites[0].attribute("name"), Some("src/lib.rs"));
+    assert_eq!(suites[1].attribute("name"), Some("src/main.rs"));
+
+    let testcase = suites[0]
+        .children()
+        .find(|n| n.has_tag_name("testcase"))
+        .unwrap();
+    assert_eq!(
+        testcase.attribute("name"),
+        Some("clippy::nonminimal_bool at src/lib.rs:3")
+    );
+    let failure = testcase
+        .children()
+        .find(|n| n.has_tag_name("failure"))
+        .unwrap();
+    assert_eq!(failure.attribute("type"), Some("warning"));
+    assert_eq!(
+        failure.text(),
+        Some("warning: comparison is useless: `a < b && b > c`\n")
+    );
+}